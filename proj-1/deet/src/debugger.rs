@@ -1,9 +1,24 @@
-use crate::debugger_command::DebuggerCommand;
+use crate::debugger_command::{tokenize, DebuggerCommand};
 use crate::inferior::Inferior;
-use crate::inferior::Status;
+use crate::inferior::{SignalHandling, Status};
+use nix::sys::signal::Signal;
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{Cmd, Config, Editor, KeyPress};
 use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Bytes disassembled either side of the current instruction when disassemble isn't given a size.
+const DISASSEMBLE_WINDOW: usize = 32;
+
+/// A breakpoint set by the user, at a debug info address.
+struct Breakpoint {
+    number: usize,
+    addr: usize,
+    /// Set by tbreak: the breakpoint is deleted the first time it is hit.
+    temporary: bool,
+}
 
 pub struct Debugger {
     target: String,
@@ -11,7 +26,22 @@ pub struct Debugger {
     readline: Editor<()>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
-    break_points: Vec<usize>,
+    break_points: Vec<Breakpoint>,
+    next_breakpoint: usize,
+    /// Index into the inferior's stack frames selected by up/down/frame; 0 is the innermost.
+    selected_frame: usize,
+    /// Arguments of the most recent run, reused by "restart" and a bare "run".
+    last_args: Vec<String>,
+    color_prompt: bool,
+    /// Expressions printed at every stop, with the numbers "undisplay" refers to them by.
+    displays: Vec<(usize, String)>,
+    next_display: usize,
+    /// Longest the inferior may run on each continue before it's stopped, from --timeout.
+    timeout: Option<Duration>,
+    /// Signals whose handling was changed with the handle command.
+    signal_handling: HashMap<Signal, SignalHandling>,
+    /// The last command entered, if an empty line should run it again.
+    last_command: Option<DebuggerCommand>,
 }
 
 impl Debugger {
@@ -30,7 +60,12 @@ impl Debugger {
         };
 
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<()>::new();
+        // Repeating a command (continue, step, ...) shouldn't fill the history with copies of it
+        let config = Config::builder().history_ignore_dups(true).build();
+        let mut readline = Editor::<()>::with_config(config);
+        // Up and down only go through earlier commands starting with what's typed so far
+        readline.bind_sequence(KeyPress::Up, Cmd::HistorySearchBackward);
+        readline.bind_sequence(KeyPress::Down, Cmd::HistorySearchForward);
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path);
 
@@ -41,6 +76,15 @@ impl Debugger {
             inferior: None,
             debug_data,
             break_points: Vec::new(),
+            next_breakpoint: 1,
+            selected_frame: 0,
+            last_args: Vec::new(),
+            color_prompt: use_color(),
+            displays: Vec::new(),
+            next_display: 1,
+            timeout: None,
+            signal_handling: HashMap::new(),
+            last_command: None,
         }
     }
 
@@ -48,63 +92,456 @@ impl Debugger {
         loop {
             match self.get_next_command(){
                 DebuggerCommand::Run(args) => self.command_run(args),
+                DebuggerCommand::Restart => self.command_run(Vec::new()),
                 DebuggerCommand::Continue => self.command_continue(),
+                DebuggerCommand::Until(line) => self.command_until(line),
+                DebuggerCommand::Step(count) => self.command_step(count, true),
+                DebuggerCommand::StepInstruction(count) => self.command_step(count, false),
                 DebuggerCommand::Backtrace => self.command_backtrace(),
-                DebuggerCommand::Break(addr) => self.command_break(addr),
+                DebuggerCommand::Break(addr) => self.command_break(addr, false),
+                DebuggerCommand::TempBreak(addr) => self.command_break(addr, true),
+                DebuggerCommand::Watch(addr) => self.command_watch(addr),
+                DebuggerCommand::Disassemble(window) => {
+                    self.command_disassemble(window.unwrap_or(DISASSEMBLE_WINDOW))
+                }
+                DebuggerCommand::Detach => self.command_detach(),
+                DebuggerCommand::Up => self.command_frame(self.selected_frame.checked_add(1)),
+                DebuggerCommand::Down => self.command_frame(self.selected_frame.checked_sub(1)),
+                DebuggerCommand::Frame(index) => self.command_frame(Some(index.unwrap_or(self.selected_frame))),
+                DebuggerCommand::InfoLine(location) => self.command_info_line(location),
+                DebuggerCommand::InfoFunctions(pattern) => self.command_info_functions(pattern),
+                DebuggerCommand::InfoArgs => self.command_info_variables(true),
+                DebuggerCommand::InfoLocals => self.command_info_variables(false),
+                DebuggerCommand::InfoProc => self.command_info_proc(),
+                DebuggerCommand::InfoBreakpoints => self.command_info_breakpoints(),
+                DebuggerCommand::Print(name) => match self.evaluate(&name, self.selected_frame) {
+                    Ok(value) => println!("{} = {}", name, value),
+                    Err(e) => println!("{}", e),
+                },
+                DebuggerCommand::Display(name) => self.command_display(name),
+                DebuggerCommand::Set(name, value) => self.command_set(&name, &value),
+                DebuggerCommand::Undisplay(number) => self.command_undisplay(number),
+                DebuggerCommand::Handle(signal, stop, pass) => self.command_handle(&signal, stop, pass),
+                DebuggerCommand::SaveBreakpoints(path) => self.command_save_breakpoints(&path),
+                DebuggerCommand::Source(path) => self.command_source(&path),
                 DebuggerCommand::Quit => {
-                    if let Some(inferior) = self.inferior.as_mut() {
-                        inferior.kill();
-                    }
+                    self.release_inferior();
                     return;
                 }
             }
         }
     }
 
-    fn command_run(&mut self, args: Vec<String>) {
-        if let Some(inferior) = self.inferior.as_mut() {
+    /// Starts debugging a process that is already running, as with `deet --pid N`.
+    pub fn attach(&mut self, pid: i32) {
+        self.release_inferior();
+        self.selected_frame = 0;
+        match Inferior::attach(&self.target, nix::unistd::Pid::from_raw(pid)) {
+            Ok(mut inferior) => {
+                println!("Attached to process {}", pid);
+                inferior.set_timeout(self.timeout);
+                inferior.set_signal_handling(self.signal_handling.clone());
+                self.inferior = Some(inferior);
+                // Report where it stopped, like after a breakpoint
+                if let Ok(rip) = self.inferior.as_ref().unwrap().instruction_ptr() {
+                    println!("Stopped at {}", self.describe_addr(rip));
+                }
+            }
+            Err(nix::Error::Sys(nix::errno::Errno::EPERM)) => println!(
+                "Could not attach to process {}: Operation not permitted. Check that you own the \
+                 process and that /proc/sys/kernel/yama/ptrace_scope allows tracing it.",
+                pid
+            ),
+            Err(e) => println!("Could not attach to process {}: {}", pid, e),
+        }
+    }
+
+    /// Stops the inferior with SIGSTOP whenever a run or continue takes longer than timeout, so
+    /// a runaway process can still be inspected.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Lets go of the current inferior, if any. A process we started is killed; one we attached
+    /// to is detached instead and keeps running.
+    fn release_inferior(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return,
+        };
+        if inferior.is_attached() {
+            let pid = inferior.pid();
+            match inferior.detach() {
+                Ok(()) => println!("Detached from process {}", pid),
+                Err(e) => println!("Error detaching from process {}: {}", pid, e),
+            }
+        } else {
             inferior.kill();
         }
-        if let Some(inferior) = Inferior::new(&self.target, &args, &mut self.break_points) {
-            // Create the inferior
-            self.inferior = Some(inferior);
-            // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-            // to the Inferior object
-            match self.inferior.as_mut().unwrap().continue_run(&mut self.break_points) {
-                Ok(message) => {
-                    match message {
-                        Status::Exited(num) => {
-                            println!("Child exited (status {})", num);
-                        },
-                        Status::Signaled(signal) => {
-                            println!("Child signaled (signal {})", signal);
-                        },
-                        Status::Stopped(signal, size) => {
-                            println!("Child stopped (signal {})", signal);
-                            println!("Stopped at {} {}", 
-                                self.debug_data.get_function_from_addr(size).expect("wrong addr"),
-                                self.debug_data.get_line_from_addr(size).expect("wrong addr")
-                            );
-                        }
-                    }
+        self.inferior = None;
+    }
+
+    fn command_run(&mut self, args: Vec<String>) {
+        // Without arguments, run again with the arguments of the previous run
+        if !args.is_empty() {
+            self.last_args = args;
+        }
+        self.release_inferior();
+        self.selected_frame = 0;
+        let break_addrs = self.break_addrs();
+        match Inferior::new(&self.target, &self.last_args, &break_addrs) {
+            Ok(mut inferior) => {
+                // Create the inferior
+                inferior.set_timeout(self.timeout);
+                inferior.set_signal_handling(self.signal_handling.clone());
+                self.inferior = Some(inferior);
+                // You may use self.inferior.as_mut().unwrap() to get a mutable reference
+                // to the Inferior object
+                match self.inferior.as_mut().unwrap().continue_run(&break_addrs) {
+                    Ok(status) => self.handle_status(status),
+                    Err(e) => { println!("{e}"); }
                 }
-                Err(e) => { println!("{e}"); }
             }
-        } else {
-            println!("Error starting subprocess");
+            Err(err) => println!("Error starting subprocess {}: {}", self.target, err),
         }
     }
 
     fn command_continue(&mut self) {
+        self.selected_frame = 0;
+        let break_addrs = self.break_addrs();
         match self.inferior.as_mut() {
             Some(inferior) => {
-                match inferior.continue_run(&mut self.break_points) {
-                    Ok(message) => {
-                        if let Status::Exited(num) = message {
-                            println!("Continue: Child exited (status {})", num);
+                match inferior.continue_run(&break_addrs) {
+                    Ok(status) => self.handle_status(status),
+                    Err(e) => { println!("{e}"); }
+                }
+            }
+            None => {
+                println!("No process running.");
+            }
+        }
+    }
+
+    /// Single-steps count source lines (by_line) or instructions, stopping early if the inferior
+    /// exits, gets a signal, or reaches a breakpoint. Only the final stop is reported.
+    fn command_step(&mut self, count: usize, by_line: bool) {
+        if self.inferior.is_none() {
+            println!("No process running.");
+            return;
+        }
+        self.selected_frame = 0;
+        let break_addrs = self.break_addrs();
+        let mut last_status = None;
+        for _ in 0..count {
+            let result = if by_line {
+                self.step_line(&break_addrs)
+            } else {
+                self.inferior.as_mut().unwrap().step_instruction(&break_addrs)
+            };
+            let status = match result {
+                Ok(status) => status,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let watch_hit = self.inferior.as_ref().is_some_and(|i| i.watchpoint_hit().is_some());
+            let keep_going = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => !break_addrs.contains(&rip) && !watch_hit,
+                _ => false,
+            };
+            last_status = Some(status);
+            if !keep_going {
+                break;
+            }
+        }
+        if let Some(status) = last_status {
+            self.handle_status(status);
+        }
+    }
+
+    /// Single-steps until the inferior reaches a different source line. Calls into code without
+    /// debug info, such as library functions, are run through at full speed rather than stepped
+    /// an instruction at a time.
+    fn step_line(&mut self, break_addrs: &[usize]) -> Result<Status, nix::Error> {
+        // Borrowed apart from the inferior, which is borrowed mutably for the whole loop
+        let debug_data = &self.debug_data;
+        let inferior = self.inferior.as_mut().unwrap();
+        let start = inferior.instruction_ptr().ok().and_then(|rip| debug_data.get_line_from_addr(rip));
+        loop {
+            let status = inferior.step_instruction(break_addrs)?;
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if !break_addrs.contains(&rip) && inferior.watchpoint_hit().is_none() => rip,
+                _ => return Ok(status),
+            };
+            match (debug_data.get_line_from_addr(rip), start.as_ref()) {
+                (Some(line), Some(start)) if line.file == start.file && line.number == start.number => {}
+                (Some(_), _) => return Ok(status),
+                // Outside of any function we know about, so we must have just been called from
+                // the line being stepped: run until the call returns
+                (None, Some(_)) if debug_data.get_function_at(rip).is_none() => {
+                    let return_addr = inferior.return_address()?;
+                    let temporary = !break_addrs.contains(&return_addr);
+                    let mut addrs = break_addrs.to_vec();
+                    if temporary {
+                        addrs.push(return_addr);
+                    }
+                    let status = inferior.continue_run(&addrs)?;
+                    if temporary {
+                        if let Status::Stopped(..) = status {
+                            inferior.remove_breakpoint(return_addr)?;
                         }
                     }
-                    Err(e) => { println!("{e}"); }
+                    match status {
+                        Status::Stopped(Signal::SIGTRAP, rip) if rip == return_addr && temporary => {}
+                        _ => return Ok(status),
+                    }
+                }
+                (None, Some(_)) => {}
+                // Stepping started without line info, so a single instruction is all we can do
+                (None, None) => return Ok(status),
+            }
+        }
+    }
+
+    /// Continues until the inferior reaches the given line of the current source file, or returns
+    /// from the current function, whichever comes first. Both are caught with breakpoints that
+    /// only live for this one continue.
+    fn command_until(&mut self, line: usize) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        let file = match inferior.instruction_ptr().ok().and_then(|rip| self.debug_data.get_nearest_line_from_addr(rip)) {
+            Some(current) => current.file,
+            None => {
+                println!("No line number information for the current location.");
+                return;
+            }
+        };
+        // The debug info may name the file by a different path than addr2line, so match on the
+        // file name alone
+        let file_name = std::path::Path::new(&file)
+            .file_name()
+            .map_or(file.clone(), |name| name.to_string_lossy().into_owned());
+        let line_addr = match self.debug_data.get_addr_for_line(Some(&file_name), line) {
+            Some(addr) => addr,
+            None => {
+                println!("Line {} is out of range for \"{}\".", line, file);
+                return;
+            }
+        };
+        let mut stops = vec![line_addr];
+        if let Some(caller) = inferior.stack_frames(&self.debug_data).ok().and_then(|frames| frames.get(1).copied()) {
+            stops.push(caller.instruction_ptr);
+        }
+
+        let mut break_addrs = self.break_addrs();
+        let temporary: Vec<usize> = stops.into_iter().filter(|addr| !break_addrs.contains(addr)).collect();
+        break_addrs.extend(&temporary);
+        self.selected_frame = 0;
+        let inferior = self.inferior.as_mut().unwrap();
+        let status = inferior.continue_run(&break_addrs);
+        if let Ok(Status::Stopped(..)) = status {
+            for &addr in &temporary {
+                if let Err(e) = inferior.remove_breakpoint(addr) {
+                    println!("Error removing breakpoint at {:#x}: {}", addr, e);
+                }
+            }
+        }
+        match status {
+            Ok(status) => self.handle_status(status),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Reports the status of the inferior after it ran. Once it has exited or been killed by a
+    /// signal it is forgotten, so later commands see that no process is running instead of
+    /// waiting on a dead child.
+    fn handle_status(&mut self, status: Status) {
+        self.print_status(&status);
+        match status {
+            Status::Exited(_) | Status::Signaled(_) => self.inferior = None,
+            Status::Stopped(signal, rip) => {
+                if signal == Signal::SIGTRAP {
+                    self.remove_temporary_breakpoint(rip);
+                }
+                // Code that wasn't mapped before, such as a shared library that has since been
+                // loaded, may be by now
+                let break_addrs = self.break_addrs();
+                if let Some(inferior) = self.inferior.as_mut() {
+                    inferior.install_breakpoints(&break_addrs);
+                }
+                self.print_displays();
+            }
+        }
+    }
+
+    /// Addresses of all breakpoints, for installing them in the inferior.
+    fn break_addrs(&self) -> Vec<usize> {
+        self.break_points.iter().map(|bp| bp.addr).collect()
+    }
+
+    /// Deletes the temporary breakpoint at addr, if the inferior just stopped on one.
+    fn remove_temporary_breakpoint(&mut self, addr: usize) {
+        let index = match self.break_points.iter().position(|bp| bp.temporary && bp.addr == addr) {
+            Some(index) => index,
+            None => return,
+        };
+        let bp = self.break_points.remove(index);
+        // Another breakpoint may share the address, in which case the 0xcc has to stay
+        if self.break_points.iter().any(|other| other.addr == addr) {
+            return;
+        }
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(e) = inferior.remove_breakpoint(addr) {
+                println!("Error removing temporary breakpoint {}: {}", bp.number, e);
+            }
+        }
+    }
+
+    /// Reads a variable visible in the stack frame at the given index and formats its value.
+    fn evaluate(&self, name: &str, frame_index: usize) -> Result<String, String> {
+        let inferior = self.inferior.as_ref().ok_or("No process running.")?;
+        let frames = inferior
+            .stack_frames(&self.debug_data)
+            .map_err(|e| format!("Error reading stack: {}", e))?;
+        let frame = frames.get(frame_index).ok_or_else(|| format!("No frame at level {}.", frame_index))?;
+        let var = self
+            .debug_data
+            .get_variable(frame.instruction_ptr, name)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+        let raw = inferior
+            .read_variable(var, frame)
+            .map_err(|e| format!("Cannot access memory of {}: {}", name, e))?;
+        Ok(var.entity_type.format_value(raw))
+    }
+
+    /// Writes a new value into an integer variable visible in the selected frame.
+    fn command_set(&mut self, name: &str, value: &str) {
+        let value = match parse_integer(value) {
+            Some(value) => value,
+            None => {
+                println!("Invalid value {}: only integer values can be assigned", value);
+                return;
+            }
+        };
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        let frame = match inferior.stack_frames(&self.debug_data) {
+            Ok(frames) => frames[self.selected_frame.min(frames.len() - 1)],
+            Err(e) => {
+                println!("Error reading stack: {}", e);
+                return;
+            }
+        };
+        let var = match self.debug_data.get_variable(frame.instruction_ptr, name) {
+            Some(var) => var,
+            None => {
+                println!("No symbol \"{}\" in current context.", name);
+                return;
+            }
+        };
+        let bytes = match var.entity_type.encode_integer(value) {
+            Some(bytes) => bytes,
+            None => {
+                println!("Cannot assign {} to {} of type {}", value, name, var.entity_type.name);
+                return;
+            }
+        };
+        if let Err(e) = inferior.write_variable(var, &frame, &bytes) {
+            println!("Cannot write memory of {}: {}", name, e);
+        }
+    }
+
+    /// Prints every display expression as "N: VAR = value", in the innermost frame.
+    fn print_displays(&self) {
+        for (number, name) in &self.displays {
+            match self.evaluate(name, 0) {
+                Ok(value) => println!("{}: {} = {}", number, name, value),
+                Err(e) => println!("{}: {}: {}", number, name, e),
+            }
+        }
+    }
+
+    fn command_display(&mut self, name: String) {
+        let number = self.next_display;
+        self.next_display += 1;
+        // Like gdb, show the value right away if there's something to read it from
+        if self.inferior.is_some() {
+            match self.evaluate(&name, self.selected_frame) {
+                Ok(value) => println!("{}: {} = {}", number, name, value),
+                Err(e) => println!("{}: {}: {}", number, name, e),
+            }
+        }
+        self.displays.push((number, name));
+    }
+
+    fn command_undisplay(&mut self, number: usize) {
+        match self.displays.iter().position(|(n, _)| *n == number) {
+            Some(index) => {
+                self.displays.remove(index);
+            }
+            None => println!("No display number {}.", number),
+        }
+    }
+
+    /// Reports how the inferior stopped or exited after a run/continue. When it stopped, the
+    /// function and source line it stopped at are printed as well.
+    fn print_status(&self, status: &Status) {
+        match status {
+            Status::Exited(num) => {
+                println!("Child exited (status {})", num);
+            },
+            Status::Signaled(signal) => {
+                println!("Child signaled (signal {})", signal);
+            },
+            Status::Stopped(signal, rip) => {
+                if let Some(hit) = self.inferior.as_ref().and_then(|i| i.watchpoint_hit()) {
+                    let addr = self.inferior.as_ref().unwrap().to_static(hit.addr);
+                    println!("Hardware watchpoint {}: *{:#x}", hit.slot + 1, addr);
+                    println!("Old value = {:#x}", hit.old_value);
+                    println!("New value = {:#x}", hit.new_value);
+                }
+                if self.inferior.as_ref().is_some_and(|i| i.timed_out()) {
+                    println!("Timed out after {:?}; the process was stopped", self.timeout.unwrap());
+                }
+                println!("Child stopped (signal {})", signal);
+                println!("Stopped at {}", self.describe_addr(*rip));
+            }
+        }
+    }
+
+    /// Describes an address as "function file:line", degrading to whatever debug info exists.
+    fn describe_addr(&self, addr: usize) -> String {
+        match (
+            self.debug_data.get_function_from_addr(addr),
+            self.debug_data.get_nearest_line_from_addr(addr),
+        ) {
+            (Some(function), Some(line)) => format!("{} {}", function, line),
+            (Some(function), None) => format!("{} ({:#x})", function, addr),
+            _ => format!("{:#x}", addr),
+        }
+    }
+
+    fn command_detach(&mut self) {
+        match self.inferior.as_mut() {
+            Some(inferior) => {
+                let pid = inferior.pid();
+                match inferior.detach() {
+                    Ok(()) => {
+                        println!("Detached from process {}", pid);
+                        self.inferior = None;
+                    }
+                    Err(e) => println!("Error detaching from process {}: {}", pid, e),
                 }
             }
             None => {
@@ -119,21 +556,351 @@ impl Debugger {
         }
     }
 
-    fn command_break(&mut self, addr: String) {
+    /// Selects the stack frame at the given index (None means the move went past the innermost
+    /// frame) and prints where it is.
+    fn command_frame(&mut self, index: Option<usize>) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        let frames = match inferior.stack_frames(&self.debug_data) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("Error reading stack: {}", e);
+                return;
+            }
+        };
+        match index {
+            Some(index) if index < frames.len() => {
+                self.selected_frame = index;
+                println!("#{} {}", index, self.describe_addr(frames[index].instruction_ptr));
+            }
+            Some(index) => println!("No frame at level {}.", index),
+            None => println!("Bottom (innermost) frame selected; you cannot go down."),
+        }
+    }
+
+    fn command_break(&mut self, addr: String, temporary: bool) {
         if !addr.starts_with("*") {
             println!("wrong address format");
             return;
         }
         let addr_0x = parse_address(&addr[1..]);
         if let Some(addr_0x) = addr_0x  {
-            self.break_points.push(addr_0x);
-            println!("Set breakpoint {} at {}", self.break_points.len(), addr);
+            let number = self.next_breakpoint;
+            self.next_breakpoint += 1;
+            self.break_points.push(Breakpoint { number, addr: addr_0x, temporary });
+            // Install it right away if there's a process, to find out whether it can be yet
+            let pending = match self.inferior.as_mut() {
+                Some(inferior) => !inferior.install_breakpoints(&[addr_0x]).is_empty(),
+                None => false,
+            };
+            let kind = if temporary { "temporary breakpoint" } else { "breakpoint" };
+            let note = if pending { " (pending: the address isn't writable yet)" } else { "" };
+            println!("Set {} {} at {}{}", kind, number, addr, note);
         } else {
             println!("wrong parse address");
             return;
         }
     }
 
+    /// Changes whether a signal stops the inferior and whether it is passed on to it, then shows
+    /// how the signal is handled. A signal that doesn't stop it is passed on, or dropped, as soon
+    /// as it arrives, without returning to the prompt.
+    fn command_handle(&mut self, name: &str, stop: Option<bool>, pass: Option<bool>) {
+        let name = name.to_uppercase();
+        let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+        let signal: Signal = match name.parse() {
+            Ok(signal) => signal,
+            Err(_) => {
+                println!("Unknown signal {}", name);
+                return;
+            }
+        };
+        if signal == Signal::SIGTRAP && (stop.is_some() || pass.is_some()) {
+            println!("SIGTRAP is used by the debugger and can't be changed");
+            return;
+        }
+        let handling = self.signal_handling.entry(signal).or_default();
+        if let Some(stop) = stop {
+            handling.stop = stop;
+        }
+        if let Some(pass) = pass {
+            handling.pass = pass;
+        }
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        println!("Signal     Stop  Pass to program");
+        println!("{:<10} {:<5} {}", name, yes_no(handling.stop), yes_no(handling.pass));
+        if let Some(inferior) = self.inferior.as_mut() {
+            inferior.set_signal_handling(self.signal_handling.clone());
+        }
+    }
+
+    /// Writes a break (or tbreak) command for each breakpoint to a file, for source to read back.
+    fn command_save_breakpoints(&self, path: &str) {
+        let commands: String = self
+            .break_points
+            .iter()
+            .map(|bp| format!("{} *{:#x}\n", if bp.temporary { "tbreak" } else { "break" }, bp.addr))
+            .collect();
+        match std::fs::write(path, commands) {
+            Ok(()) => println!("Saved {} breakpoints to {}", self.break_points.len(), path),
+            Err(err) => println!("Could not write {}: {}", path, err),
+        }
+    }
+
+    /// Runs the break and tbreak commands in a file, such as one written by save breakpoints.
+    /// Blank lines and lines starting with # are skipped; anything else is reported and skipped.
+    fn command_source(&mut self, path: &str) {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                println!("Could not read {}: {}", path, err);
+                return;
+            }
+        };
+        for (index, line) in contents.lines().enumerate() {
+            let tokens = tokenize(line);
+            let tokens: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            match DebuggerCommand::from_tokens(&tokens) {
+                Some(DebuggerCommand::Break(addr)) => self.command_break(addr, false),
+                Some(DebuggerCommand::TempBreak(addr)) => self.command_break(addr, true),
+                _ => println!("{}:{}: not a breakpoint command: {}", path, index + 1, line),
+            }
+        }
+    }
+
+    fn command_watch(&mut self, addr: String) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        if !addr.starts_with("*") {
+            println!("wrong address format");
+            return;
+        }
+        // Like break *ADDR, the address is as in the debug info, whatever the load bias
+        match parse_address(&addr[1..]).map(|addr_0x| inferior.set_watchpoint(inferior.to_runtime(addr_0x))) {
+            Some(Ok(slot)) => println!("Hardware watchpoint {}: {}", slot + 1, addr),
+            Some(Err(e)) => println!("Cannot set watchpoint: {}", e),
+            None => println!("wrong parse address"),
+        }
+    }
+
+    /// Prints the instructions within window bytes either side of the current instruction, which
+    /// is marked with "=>". x86 can't be decoded backwards, so decoding starts from the entry of
+    /// the current function when there is debug info for it, and from rip itself otherwise.
+    fn command_disassemble(&self, window: usize) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        let rip = match inferior.instruction_ptr() {
+            Ok(rip) => rip,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let function = self.debug_data.get_function_at(rip);
+        let start = function.map_or(rip, |func| func.address);
+        let end = rip.saturating_add(window);
+        let bytes = match inferior.read_bytes(inferior.to_runtime(start), end - start) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Cannot access memory at address {:#x}: {}", start, e);
+                return;
+            }
+        };
+
+        let mut decoder = Decoder::with_ip(64, &bytes, start as u64, DecoderOptions::NONE);
+        let mut formatter = GasFormatter::new();
+        let mut instruction = Instruction::default();
+        let mut text = String::new();
+        while decoder.can_decode() {
+            decoder.decode_out(&mut instruction);
+            let addr = instruction.ip() as usize;
+            if addr + instruction.len() <= rip.saturating_sub(window) {
+                continue;
+            }
+            // The last instruction may have been cut off by the end of the window or of memory
+            if instruction.is_invalid() && decoder.position() == bytes.len() {
+                break;
+            }
+            text.clear();
+            formatter.format(&instruction, &mut text);
+            let marker = if addr == rip { "=>" } else { "  " };
+            let offset = function.map_or(String::new(), |func| format!(" <{}+{}>", func.name, addr - func.address));
+            println!("{} {:#x}{}:\t{}", marker, addr, offset, text);
+        }
+        if bytes.len() < end - start {
+            println!("Cannot access memory at address {:#x}", start + bytes.len());
+        }
+    }
+
+    /// Lists the breakpoints. While there is a process, breakpoints that couldn't be written into
+    /// its memory yet are shown as pending.
+    fn command_info_breakpoints(&self) {
+        if self.break_points.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        println!("Num     Type           Disp Address            What");
+        for bp in &self.break_points {
+            let disp = if bp.temporary { "del" } else { "keep" };
+            let address = match self.inferior.as_ref() {
+                Some(inferior) if !inferior.is_installed(bp.addr) => String::from("<PENDING>"),
+                _ => format!("{:#018x}", bp.addr),
+            };
+            println!("{:<7} {:<14} {:<4} {:<18} {}", bp.number, "breakpoint", disp, address, self.describe_addr(bp.addr));
+        }
+    }
+
+    /// Prints the inferior's pid, state, executable and arguments.
+    fn command_info_proc(&self) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process");
+                return;
+            }
+        };
+        // An attached process wasn't started with our arguments, so ask the kernel
+        let args = if inferior.is_attached() {
+            inferior.cmdline().unwrap_or_default()
+        } else {
+            std::iter::once(self.target.clone()).chain(self.last_args.iter().cloned()).collect()
+        };
+        let quoted: Vec<String> = args.iter().map(|arg| format!("'{}'", arg)).collect();
+        println!("process {}", inferior.pid());
+        println!("state = {}", inferior.state().unwrap_or_else(|| String::from("unknown")));
+        println!("exe = '{}'", self.target);
+        println!("cmdline = {}", quoted.join(" "));
+    }
+
+    /// Prints the source line for "*ADDR" or the entry of a function, without running to it.
+    fn command_info_line(&self, location: String) {
+        let addr = if let Some(addr) = location.strip_prefix('*') {
+            match parse_address(addr) {
+                Some(addr) => addr,
+                None => {
+                    println!("wrong parse address");
+                    return;
+                }
+            }
+        } else {
+            match self.debug_data.get_addr_for_function(None, &location) {
+                Some(addr) => addr,
+                None => {
+                    println!("Function \"{}\" not defined.", location);
+                    return;
+                }
+            }
+        };
+        match self.debug_data.get_line_from_addr(addr) {
+            Some(line) => {
+                let function = self
+                    .debug_data
+                    .get_function_from_addr(addr)
+                    .map_or(String::new(), |function| format!(" <{}>", function));
+                println!("Line {} of \"{}\" is at address {:#x}{}.", line.number, line.file, addr, function);
+            }
+            None => println!("No line number information available for address {:#x}", addr),
+        }
+    }
+
+    /// Prints the value of every parameter (for "info args") or local variable ("info locals") of
+    /// the function running in the selected frame.
+    fn command_info_variables(&self, parameters: bool) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No process running.");
+                return;
+            }
+        };
+        let frame = match inferior.stack_frames(&self.debug_data) {
+            Ok(frames) => frames[self.selected_frame.min(frames.len() - 1)],
+            Err(e) => {
+                println!("Error reading stack: {}", e);
+                return;
+            }
+        };
+        let variables = match self.debug_data.get_frame_variables(frame.instruction_ptr) {
+            Some(variables) => variables,
+            None => {
+                println!("No symbol information available for this frame.");
+                return;
+            }
+        };
+        let mut found = false;
+        for var in variables.iter().filter(|var| var.is_parameter == parameters) {
+            found = true;
+            match inferior.read_variable(var, &frame) {
+                Ok(raw) => println!("{} = {}", var.name, var.entity_type.format_value(raw)),
+                Err(e) => println!("{} = <cannot read memory: {}>", var.name, e),
+            }
+        }
+        if !found {
+            println!("{}", if parameters { "No arguments." } else { "No locals." });
+        }
+    }
+
+    /// Lists the functions with debug info, sorted by name, keeping only those whose name
+    /// contains the pattern if one is given.
+    fn command_info_functions(&self, pattern: Option<String>) {
+        let mut functions = self.debug_data.list_functions();
+        if let Some(pattern) = pattern.as_ref() {
+            functions.retain(|(name, _)| name.contains(pattern.as_str()));
+        }
+        functions.sort();
+        match pattern {
+            Some(pattern) => println!("All functions matching \"{}\":", pattern),
+            None => println!("All defined functions:"),
+        }
+        for (name, addr) in &functions {
+            println!("{:#018x}  {}", addr, name);
+        }
+        println!("{} function{}.", functions.len(), if functions.len() == 1 { "" } else { "s" });
+    }
+
+    /// Builds the prompt string. It shows "(deet)" when no inferior is running, and the name of
+    /// the function the inferior is stopped in otherwise.
+    fn prompt(&self) -> String {
+        let state = match self.inferior.as_ref() {
+            None => return self.paint("(deet) ", "1"),
+            Some(inferior) => match inferior.instruction_ptr() {
+                Ok(rip) => self
+                    .debug_data
+                    .get_function_from_addr(rip)
+                    .unwrap_or_else(|| "stopped".to_string()),
+                Err(_) => "running".to_string(),
+            },
+        };
+        self.paint(&format!("(deet:{}) ", state), "1;33")
+    }
+
+    /// Wraps text in the given ANSI SGR code, unless colored output is disabled.
+    fn paint(&self, text: &str, sgr: &str) -> String {
+        if self.color_prompt {
+            format!("\x1b[{}m{}\x1b[0m", sgr, text)
+        } else {
+            text.to_string()
+        }
+    }
+
     /// This function prompts the user to enter a command, and continues re-prompting until the user
     /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
     ///
@@ -141,7 +908,8 @@ impl Debugger {
     fn get_next_command(&mut self) -> DebuggerCommand {
         loop {
             // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
+            let prompt = self.prompt();
+            match self.readline.readline(&prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. We're going to ignore it
                     println!("Type \"quit\" to exit");
@@ -155,17 +923,25 @@ impl Debugger {
                 }
                 Ok(line) => {
                     if line.trim().len() == 0 {
-                        continue;
+                        // Enter alone repeats the last step, continue, up or down
+                        match self.last_command.clone() {
+                            Some(cmd) => return cmd,
+                            None => continue,
+                        }
                     }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
+                    // Only rewrite the history file if the line was actually added to it
+                    if self.readline.add_history_entry(line.as_str()) {
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
+                    let tokens = tokenize(&line);
+                    let tokens: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
                     if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                        self.last_command = Some(cmd.clone()).filter(|cmd| cmd.repeats_on_empty_line());
                         return cmd;
                     } else {
                         println!("Unrecognized command.");
@@ -176,6 +952,24 @@ impl Debugger {
     }
 }
 
+/// Colors are only used when stdout is a terminal and the NO_COLOR environment variable is unset.
+fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+}
+
+/// Parses a decimal or 0x-prefixed hexadecimal integer, optionally negative.
+fn parse_integer(value: &str) -> Option<i128> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let magnitude = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
 fn parse_address(addr: &str) -> Option<usize> {
     let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
         &addr[2..]
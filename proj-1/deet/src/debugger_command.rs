@@ -1,9 +1,42 @@
+#[derive(Clone)]
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
+    Restart,
     Continue,
+    /// Single-step this many source lines.
+    Step(usize),
+    /// Single-step this many instructions.
+    StepInstruction(usize),
+    Detach,
     Backtrace,
     Break(String),
+    TempBreak(String),
+    Until(usize),
+    Watch(String),
+    Up,
+    Down,
+    Frame(Option<usize>),
+    /// Disassembles this many bytes either side of the current instruction, if given.
+    Disassemble(Option<usize>),
+    InfoLine(String),
+    InfoFunctions(Option<String>),
+    InfoArgs,
+    InfoLocals,
+    InfoProc,
+    InfoBreakpoints,
+    Print(String),
+    Display(String),
+    Undisplay(usize),
+    /// Assigns a value to a variable: the variable name and the value as typed.
+    Set(String, String),
+    /// Changes how a signal is handled: the signal's name, then whether it should stop the
+    /// inferior and whether it should be passed to it, where given.
+    Handle(String, Option<bool>, Option<bool>),
+    /// Writes the breakpoints to this file, as break commands.
+    SaveBreakpoints(String),
+    /// Sets the breakpoints listed in this file, as written by save breakpoints.
+    Source(String),
 }
 
 impl DebuggerCommand {
@@ -16,11 +49,236 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "restart" => Some(DebuggerCommand::Restart),
             "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "s" | "step" => Some(DebuggerCommand::Step(parse_count(tokens.get(1))?)),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction(parse_count(tokens.get(1))?)),
+            "detach" => Some(DebuggerCommand::Detach),
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
             "b" | "break" => Some(DebuggerCommand::Break(tokens[1].to_string())),
+            "u" | "until" => Some(DebuggerCommand::Until(tokens.get(1)?.parse().ok()?)),
+            "tbreak" => Some(DebuggerCommand::TempBreak(tokens.get(1)?.to_string())),
+            "up" => Some(DebuggerCommand::Up),
+            "down" => Some(DebuggerCommand::Down),
+            "f" | "frame" => match tokens.get(1) {
+                Some(index) => Some(DebuggerCommand::Frame(Some(index.parse().ok()?))),
+                None => Some(DebuggerCommand::Frame(None)),
+            },
+            "disas" | "disassemble" => match tokens.get(1) {
+                Some(window) => Some(DebuggerCommand::Disassemble(Some(window.parse().ok()?))),
+                None => Some(DebuggerCommand::Disassemble(None)),
+            },
+            "watch" => Some(DebuggerCommand::Watch(tokens.get(1)?.to_string())),
+            "p" | "print" => Some(DebuggerCommand::Print(tokens.get(1)?.to_string())),
+            "display" => Some(DebuggerCommand::Display(tokens.get(1)?.to_string())),
+            "undisplay" => Some(DebuggerCommand::Undisplay(tokens.get(1)?.parse().ok()?)),
+            "set" => {
+                // Accept "set var x = 1", "set x = 1" and "set x=1" alike
+                let rest = if tokens.get(1) == Some(&"var") { &tokens[2..] } else { &tokens[1..] };
+                let assignment = rest.join(" ");
+                let (name, value) = assignment.split_once('=')?;
+                let (name, value) = (name.trim(), value.trim());
+                if name.is_empty() || value.is_empty() {
+                    return None;
+                }
+                Some(DebuggerCommand::Set(name.to_string(), value.to_string()))
+            }
+            "handle" => {
+                let signal = tokens.get(1)?.to_string();
+                let (mut stop, mut pass) = (None, None);
+                for action in &tokens[2..] {
+                    match *action {
+                        "stop" => stop = Some(true),
+                        "nostop" => stop = Some(false),
+                        "pass" | "noignore" => pass = Some(true),
+                        "nopass" | "ignore" => pass = Some(false),
+                        _ => return None,
+                    }
+                }
+                Some(DebuggerCommand::Handle(signal, stop, pass))
+            }
+            "save" => match *tokens.get(1)? {
+                "b" | "break" | "breakpoints" => Some(DebuggerCommand::SaveBreakpoints(tokens.get(2)?.to_string())),
+                _ => None,
+            },
+            "source" => Some(DebuggerCommand::Source(tokens.get(1)?.to_string())),
+            "i" | "info" => match *tokens.get(1)? {
+                "line" => Some(DebuggerCommand::InfoLine(tokens.get(2)?.to_string())),
+                "args" => Some(DebuggerCommand::InfoArgs),
+                "locals" => Some(DebuggerCommand::InfoLocals),
+                "proc" => Some(DebuggerCommand::InfoProc),
+                "b" | "break" | "breakpoints" => Some(DebuggerCommand::InfoBreakpoints),
+                "functions" => Some(DebuggerCommand::InfoFunctions(tokens.get(2).map(|s| s.to_string()))),
+                _ => None,
+            },
             // Default case:
             _ => None,
         }
     }
+
+    /// Whether an empty line at the prompt runs this command again, as in gdb. Only moving
+    /// through the program or the stack repeats; anything else, and in particular run and quit,
+    /// is never rerun by accident.
+    pub fn repeats_on_empty_line(&self) -> bool {
+        matches!(
+            self,
+            DebuggerCommand::Continue
+                | DebuggerCommand::Step(_)
+                | DebuggerCommand::StepInstruction(_)
+                | DebuggerCommand::Up
+                | DebuggerCommand::Down
+        )
+    }
+}
+
+/// Parses the optional repeat count of a stepping command, which defaults to 1. Returns None if
+/// it isn't a positive number.
+fn parse_count(token: Option<&&str>) -> Option<usize> {
+    match token {
+        Some(token) => token.parse().ok().filter(|&count| count > 0),
+        None => Some(1),
+    }
+}
+
+/// Splits a command line into tokens on whitespace. Double quotes group text containing spaces
+/// into a single token (so `run "hello world"` passes one argument), and a backslash makes the
+/// next character literal, including a quote or another backslash.
+pub fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    // Tracked separately from token.is_empty() so that "" still produces an (empty) argument
+    let mut in_token = false;
+    let mut in_quotes = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    token.push(escaped);
+                }
+                in_token = true;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            c => {
+                token.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_token {
+        tokens.push(token);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_set() {
+        let parse = |line: &str| match DebuggerCommand::from_tokens(&line.split_whitespace().collect()) {
+            Some(DebuggerCommand::Set(name, value)) => Some((name, value)),
+            _ => None,
+        };
+        let expected = Some(("x".to_string(), "42".to_string()));
+        assert_eq!(parse("set var x = 42"), expected);
+        assert_eq!(parse("set x = 42"), expected);
+        assert_eq!(parse("set x=42"), expected);
+        assert_eq!(parse("set x ="), None);
+        assert_eq!(parse("set x"), None);
+    }
+
+    #[test]
+    fn test_parse_step_count() {
+        let parse = |line: &str| match DebuggerCommand::from_tokens(&line.split_whitespace().collect()) {
+            Some(DebuggerCommand::Step(count)) => Some(("step", count)),
+            Some(DebuggerCommand::StepInstruction(count)) => Some(("stepi", count)),
+            _ => None,
+        };
+        assert_eq!(parse("step"), Some(("step", 1)));
+        assert_eq!(parse("s 5"), Some(("step", 5)));
+        assert_eq!(parse("stepi 100"), Some(("stepi", 100)));
+        assert_eq!(parse("si"), Some(("stepi", 1)));
+        assert_eq!(parse("stepi 0"), None);
+        assert_eq!(parse("step x"), None);
+    }
+
+    #[test]
+    fn test_parse_handle() {
+        let parse = |line: &str| match DebuggerCommand::from_tokens(&line.split_whitespace().collect()) {
+            Some(DebuggerCommand::Handle(signal, stop, pass)) => Some((signal, stop, pass)),
+            _ => None,
+        };
+        let sigusr1 = || "SIGUSR1".to_string();
+        assert_eq!(parse("handle SIGUSR1 nostop pass"), Some((sigusr1(), Some(false), Some(true))));
+        assert_eq!(parse("handle SIGUSR1 stop nopass"), Some((sigusr1(), Some(true), Some(false))));
+        assert_eq!(parse("handle SIGUSR1 ignore"), Some((sigusr1(), None, Some(false))));
+        assert_eq!(parse("handle SIGUSR1"), Some((sigusr1(), None, None)));
+        assert_eq!(parse("handle SIGUSR1 sometimes"), None);
+        assert_eq!(parse("handle"), None);
+    }
+
+    #[test]
+    fn test_parse_save_and_source() {
+        let parse = |line: &str| match DebuggerCommand::from_tokens(&line.split_whitespace().collect()) {
+            Some(DebuggerCommand::SaveBreakpoints(path)) => Some(("save", path)),
+            Some(DebuggerCommand::Source(path)) => Some(("source", path)),
+            _ => None,
+        };
+        assert_eq!(parse("save breakpoints bps.txt"), Some(("save", "bps.txt".to_string())));
+        assert_eq!(parse("save b bps.txt"), Some(("save", "bps.txt".to_string())));
+        assert_eq!(parse("source bps.txt"), Some(("source", "bps.txt".to_string())));
+        assert_eq!(parse("save breakpoints"), None);
+        assert_eq!(parse("save displays x"), None);
+        assert_eq!(parse("source"), None);
+    }
+
+    #[test]
+    fn test_repeats_on_empty_line() {
+        let repeats = |line: &str| {
+            DebuggerCommand::from_tokens(&line.split_whitespace().collect()).unwrap().repeats_on_empty_line()
+        };
+        assert!(repeats("step"));
+        assert!(repeats("si 3"));
+        assert!(repeats("continue"));
+        assert!(repeats("up"));
+        assert!(!repeats("run"));
+        assert!(!repeats("quit"));
+        assert!(!repeats("print x"));
+        assert!(!repeats("break *0x401136"));
+    }
+
+    #[test]
+    fn test_tokenize_plain() {
+        assert_eq!(tokenize("run  a b\tc "), vec!["run", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_tokenize_quoted() {
+        assert_eq!(tokenize("run \"hello world\" x"), vec!["run", "hello world", "x"]);
+        assert_eq!(tokenize("run \"\" x"), vec!["run", "", "x"]);
+        // An unterminated quote runs to the end of the line
+        assert_eq!(tokenize("run \"a b"), vec!["run", "a b"]);
+    }
+
+    #[test]
+    fn test_tokenize_escaped() {
+        assert_eq!(tokenize(r#"run say\ hi \"quoted\" back\\slash"#), vec!["run", "say hi", "\"quoted\"", "back\\slash"]);
+        assert_eq!(tokenize(r#"run "a \"b\" c""#), vec!["run", "a \"b\" c"]);
+    }
+
+    #[test]
+    fn test_tokenize_mixed() {
+        assert_eq!(tokenize(r#"run --name="Jane Doe" plain "two words"x"#), vec!["run", "--name=Jane Doe", "plain", "two wordsx"]);
+    }
 }
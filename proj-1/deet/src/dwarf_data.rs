@@ -1,9 +1,14 @@
 use crate::gimli_wrapper;
 use addr2line::Context;
 use object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
+/// Most addresses worth caching lookups for; past this, the caches are cleared and refilled.
+const LOOKUP_CACHE_SIZE: usize = 4096;
+
 #[derive(Debug)]
 pub enum Error {
     ErrorOpeningFile,
@@ -13,6 +18,13 @@ pub enum Error {
 pub struct DwarfData {
     files: Vec<File>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    /// (start address, end address, file index, function index) of every function, sorted by
+    /// start address, so get_function_at can binary search instead of scanning every file.
+    function_index: Vec<(usize, usize, usize, usize)>,
+    /// Results of earlier addr2line lookups. Stopping, stepping and backtraces keep asking
+    /// about the same few addresses, and addr2line has to walk the DWARF each time.
+    function_name_cache: RefCell<HashMap<usize, Option<String>>>,
+    line_cache: RefCell<HashMap<usize, Option<Line>>>,
 }
 
 impl fmt::Debug for DwarfData {
@@ -38,12 +50,40 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        let files = gimli_wrapper::load_file(&object, endian)?;
+        let mut function_index: Vec<(usize, usize, usize, usize)> = files
+            .iter()
+            .enumerate()
+            .flat_map(|(file_index, file)| {
+                file.functions.iter().enumerate().map(move |(func_index, func)| {
+                    (func.address, func.address + func.text_length, file_index, func_index)
+                })
+            })
+            .collect();
+        function_index.sort();
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
+            files,
             addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            function_index,
+            function_name_cache: RefCell::new(HashMap::new()),
+            line_cache: RefCell::new(HashMap::new()),
         })
     }
 
+    /// Returns the cached result of lookup(addr), calling it only the first time addr is seen.
+    fn cached<V: Clone>(cache: &RefCell<HashMap<usize, V>>, addr: usize, lookup: impl FnOnce() -> V) -> V {
+        if let Some(value) = cache.borrow().get(&addr) {
+            return value.clone();
+        }
+        let value = lookup();
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= LOOKUP_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(addr, value.clone());
+        value
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
         self.files.iter().find(|f| {
@@ -89,6 +129,10 @@ impl DwarfData {
 
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
+        DwarfData::cached(&self.line_cache, curr_addr, || self.lookup_line(curr_addr))
+    }
+
+    fn lookup_line(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
             .find_location(curr_addr.try_into().unwrap())
@@ -100,8 +144,27 @@ impl DwarfData {
         })
     }
 
+    /// Like get_line_from_addr, but an address with no line of its own, such as one between
+    /// line table rows in optimized code, falls back to the row that most closely precedes it in
+    /// the same function. Any address in a function with debug info maps to some line this way.
+    pub fn get_nearest_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
+        self.get_line_from_addr(curr_addr).or_else(|| {
+            let function = self.get_function_at(curr_addr)?;
+            let rows = self.files.iter().flat_map(|file| file.lines.iter());
+            let line = nearest_line(rows, function.address, curr_addr)?;
+            Some(Line {
+                address: curr_addr,
+                ..line.clone()
+            })
+        })
+    }
+
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
+        DwarfData::cached(&self.function_name_cache, curr_addr, || self.lookup_function_name(curr_addr))
+    }
+
+    fn lookup_function_name(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
             .find_frames(curr_addr.try_into().unwrap())
@@ -111,6 +174,46 @@ impl DwarfData {
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the function whose code contains the given address.
+    pub fn get_function_at(&self, curr_addr: usize) -> Option<&Function> {
+        // The last function starting at or before the address is the only one that can contain it
+        let candidates = self.function_index.partition_point(|&(start, ..)| start <= curr_addr);
+        let &(_, end, file_index, func_index) = self.function_index[..candidates].last()?;
+        if curr_addr < end {
+            Some(&self.files[file_index].functions[func_index])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the parameters and local variables of the function containing the given address,
+    /// with their frame pointer relative locations, or None if it has no debug info.
+    pub fn get_frame_variables(&self, curr_addr: usize) -> Option<&[Variable]> {
+        Some(&self.get_function_at(curr_addr)?.variables)
+    }
+
+    /// Finds the variable called name that is visible at the given address: a local variable or
+    /// parameter of the function containing it, or else a global variable.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        self.get_function_at(curr_addr)
+            .and_then(|func| func.variables.iter().find(|var| var.name == name))
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .flat_map(|file| file.global_variables.iter())
+                    .find(|var| var.name == name)
+            })
+    }
+
+    /// Returns the name and entry address of every function with debug info, in file order.
+    pub fn list_functions(&self) -> Vec<(&str, usize)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .map(|func| (func.name.as_str(), func.address))
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -148,6 +251,14 @@ impl DwarfData {
     }
 }
 
+/// Returns the line table row with the highest address in start..=addr. Rows aren't necessarily
+/// in address order, so all of them are looked at.
+fn nearest_line<'a>(rows: impl IntoIterator<Item = &'a Line>, start: usize, addr: usize) -> Option<&'a Line> {
+    rows.into_iter()
+        .filter(|row| (start..=addr).contains(&row.address))
+        .max_by_key(|row| row.address)
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
@@ -161,6 +272,45 @@ impl Type {
             size: size,
         }
     }
+
+    /// Formats a raw little-endian value of this type; only the low `size` bytes are used.
+    pub fn format_value(&self, raw: u64) -> String {
+        let bits = 8 * self.size.clamp(1, 8) as u32;
+        let value = if bits == 64 { raw } else { raw & ((1 << bits) - 1) };
+        match (self.name.as_str(), self.size) {
+            ("float", 4) => format!("{}", f32::from_bits(value as u32)),
+            ("double", 8) => format!("{}", f64::from_bits(value)),
+            (name, _) if name.contains("unsigned") && name.contains("char") && self.size == 1 => {
+                format!("{} '{}'", value as u8, (value as u8).escape_ascii())
+            }
+            (name, _) if name.contains("char") && self.size == 1 => {
+                format!("{} '{}'", value as i8, (value as u8).escape_ascii())
+            }
+            (name, _) if name.contains("unsigned") || name == "_Bool" => format!("{}", value),
+            // Sign-extend everything else from its declared width
+            _ => format!("{}", ((value << (64 - bits)) as i64) >> (64 - bits)),
+        }
+    }
+
+    /// Encodes an integer as the little-endian bytes of a value of this type. Returns None if
+    /// this isn't an integer type of a supported size or the value is out of its range.
+    pub fn encode_integer(&self, value: i128) -> Option<Vec<u8>> {
+        if self.name == "float" || self.name == "double" || ![1, 2, 4, 8].contains(&self.size) {
+            return None;
+        }
+        let bits = 8 * self.size as u32;
+        let (min, max) = if self.name == "_Bool" {
+            (0, 1)
+        } else if self.name.contains("unsigned") {
+            (0, (1i128 << bits) - 1)
+        } else {
+            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+        };
+        if value < min || value > max {
+            return None;
+        }
+        Some(value.to_le_bytes()[..self.size].to_vec())
+    }
 }
 
 #[derive(Clone)]
@@ -191,6 +341,18 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool, // Formal parameter rather than a local/global variable
+}
+
+impl Variable {
+    /// Returns where this variable lives in memory, given the base pointer of the frame it
+    /// belongs to. With -fno-omit-frame-pointer the DWARF frame base is rbp + 16.
+    pub fn address(&self, base_ptr: usize) -> usize {
+        match self.location {
+            Location::Address(addr) => addr,
+            Location::FramePointerOffset(offset) => (base_ptr as isize + 16 + offset) as usize,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -223,4 +385,38 @@ impl fmt::Display for Line {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
 
+    fn row(number: usize, address: usize) -> Line {
+        Line { file: "sample.c".to_string(), number, address }
+    }
+
+    #[test]
+    fn test_nearest_line() {
+        // Out of address order, as rows from several sequences can be
+        let rows = vec![row(10, 0x1000), row(12, 0x1010), row(11, 0x1008), row(20, 0x2000)];
+        let nearest = |start, addr| nearest_line(&rows, start, addr).map(|line| line.number);
+        assert_eq!(nearest(0x1000, 0x1000), Some(10));
+        assert_eq!(nearest(0x1000, 0x1004), Some(10));
+        assert_eq!(nearest(0x1000, 0x100c), Some(11));
+        assert_eq!(nearest(0x1000, 0x1fff), Some(12));
+        assert_eq!(nearest(0x1000, 0x2004), Some(20));
+        // Nothing precedes the address within the function
+        assert_eq!(nearest(0x1000, 0x0fff), None);
+        assert_eq!(nearest(0x1800, 0x1900), None);
+        assert_eq!(nearest_line(&[], 0, 0x1000), None);
+    }
+
+    #[test]
+    fn test_format_value() {
+        let ty = |name: &str, size| Type::new(name.to_string(), size);
+        assert_eq!(ty("char", 1).format_value(0x41), "65 'A'");
+        assert_eq!(ty("char", 1).format_value(200), "-56 '\\xc8'");
+        assert_eq!(ty("unsigned char", 1).format_value(200), "200 '\\xc8'");
+        assert_eq!(ty("int", 4).format_value(0xffff_ffff), "-1");
+        assert_eq!(ty("unsigned int", 4).format_value(0x1_ffff_ffff), "4294967295");
+        assert_eq!(ty("_Bool", 1).format_value(1), "1");
+    }
+}
@@ -6,12 +6,18 @@ use std::process::Child;
 use std::process::Command;
 use std::os::unix::process::CommandExt;
 use std::mem::size_of;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::dwarf_data;
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
-    /// current instruction pointer that it is stopped at.
+    /// current instruction pointer that it is stopped at (as a debug info address, see
+    /// Inferior::to_static).
     Stopped(signal::Signal, usize),
 
     /// Indicates inferior exited normally. Contains the exit status code.
@@ -31,100 +37,815 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// ELF e_type of position-independent executables and shared objects.
+const ET_DYN: u16 = 3;
+
+/// Whether the ELF file at path is position-independent, i.e. is loaded at an address chosen at
+/// run time rather than at the addresses it was linked for.
+fn is_position_independent(path: &Path) -> bool {
+    let mut header = [0u8; 18];
+    match std::fs::File::open(path).and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header)) {
+        Ok(()) => u16::from_le_bytes([header[16], header[17]]) == ET_DYN,
+        Err(_) => false,
+    }
+}
+
+/// Finds the load bias of the target executable and the address range it is mapped at, from
+/// /proc/pid/maps. A position-independent executable's debug info addresses are offsets from its
+/// first mapping, so the start of that mapping is the bias; other executables run at their link
+/// addresses and have no bias.
+fn find_image(target: &str, pid: Pid) -> Option<(usize, Range<usize>)> {
+    let path = std::fs::canonicalize(target).ok()?;
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).ok()?;
+    let mut base = None;
+    let mut image: Option<Range<usize>> = None;
+    for line in maps.lines() {
+        // address perms offset dev inode, then the path after a run of padding spaces
+        let fields: Vec<&str> = line.splitn(6, ' ').collect();
+        if fields.len() < 6 || Path::new(fields[5].trim_start()) != path {
+            continue;
+        }
+        let (start, end) = fields[0].split_once('-')?;
+        let start = usize::from_str_radix(start, 16).ok()?;
+        let end = usize::from_str_radix(end, 16).ok()?;
+        if base.is_none() && usize::from_str_radix(fields[2], 16).ok()? == 0 {
+            base = Some(start);
+        }
+        image = Some(match image {
+            Some(image) => image.start.min(start)..image.end.max(end),
+            None => start..end,
+        });
+    }
+    let bias = if is_position_independent(&path) { base? } else { 0 };
+    Some((bias, image?))
+}
+
+/// Offset of u_debugreg within struct user on x86-64, for PTRACE_PEEKUSER/PTRACE_POKEUSER.
+const DEBUG_REG_OFFSET: usize = 848;
+
+/// How often a continue with a timeout checks whether the inferior has stopped.
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// DR0-DR3 hold watch addresses; DR6 reports which fired and DR7 enables them.
+const NUM_WATCHPOINTS: usize = 4;
+const DR_STATUS: usize = 6;
+const DR_CONTROL: usize = 7;
+
+/// Describes a watchpoint that fired because the watched word changed.
+pub struct WatchpointHit {
+    pub slot: usize,
+    pub addr: usize,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
+/// What to do when the inferior receives a signal, as set with the handle command. Signals
+/// without an entry get the default: they stop the inferior and are not delivered to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignalHandling {
+    /// Return control to the debugger when the signal arrives.
+    pub stop: bool,
+    /// Deliver the signal to the inferior when it resumes, rather than discarding it.
+    pub pass: bool,
+}
+
+impl Default for SignalHandling {
+    fn default() -> Self {
+        SignalHandling { stop: true, pass: false }
+    }
+}
+
+/// A stack frame found by walking the base pointer chain.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub instruction_ptr: usize,
+    pub base_ptr: usize,
+}
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// The process we spawned, or None if we attached to a process that was already running.
+    child: Option<Child>,
+    /// Installed breakpoints, mapping each address to the original byte overwritten by 0xcc.
+    breakpoints: HashMap<usize, u8>,
+    /// Hardware watchpoints by debug register slot: the watched address and its last value.
+    watchpoints: [Option<(usize, u64)>; NUM_WATCHPOINTS],
+    watchpoint_hit: Option<WatchpointHit>,
+    /// Longest a continue may run before the inferior is stopped with SIGSTOP.
+    timeout: Option<Duration>,
+    /// Set when the last continue was cut short by the timeout.
+    timed_out: bool,
+    signal_handling: HashMap<signal::Signal, SignalHandling>,
+    /// A signal that stopped the inferior and is to be passed to it when it resumes.
+    pending_signal: Option<signal::Signal>,
+    /// How far the executable was relocated from its debug info addresses (0 unless it is PIE).
+    load_bias: usize,
+    /// Where the executable's own code and data are mapped; only addresses in here are
+    /// translated, since libraries have no debug info of their own to translate to.
+    image: Range<usize>,
 }
 
 impl Inferior {
-    /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, break_points: &mut Vec<usize>) -> Option<Inferior> {
+    /// Attempts to start a new inferior process. Returns the error if the target couldn't be run,
+    /// such as permission denied or an exec format error for a file that isn't an executable for
+    /// this machine.
+    pub fn new(target: &str, args: &Vec<String>, break_points: &[usize]) -> std::io::Result<Inferior> {
         let mut command = Command::new(target);
         command.args(args);
         unsafe {
             command.pre_exec(child_traceme);
         }
-        let child = command.spawn().ok()?;
-        let mut inferior = Inferior { child };
-        for break_point in break_points.into_iter() {
-            if let Err(e) = inferior.write_byte(*break_point, 0xcc) {
-                println!("Error setting breakpoint: {}", e);
-            }
+        // A failed exec is reported back by spawn, with the errno it failed with
+        let child = command.spawn()?;
+        let mut inferior = Inferior::from_process(Pid::from_raw(child.id() as i32), Some(child));
+        // The child stops with SIGTRAP on exec; its memory can only be patched once it's stopped
+        inferior.wait(None).map_err(std::io::Error::other)?;
+        // By now the executable is mapped, though not yet relocated or run
+        inferior.locate_image(target);
+        inferior.install_breakpoints(break_points);
+        Ok(inferior)
+    }
+
+    /// Starts tracing a process that is already running, and waits for it to stop. target is
+    /// the executable it runs, for finding where it was loaded. The process wasn't started by
+    /// us, so it should be detached rather than killed when we're done with it.
+    pub fn attach(target: &str, pid: Pid) -> Result<Inferior, nix::Error> {
+        ptrace::attach(pid)?;
+        let mut inferior = Inferior::from_process(pid, None);
+        // PTRACE_ATTACH stops the process with SIGSTOP
+        inferior.wait(None)?;
+        inferior.locate_image(target);
+        Ok(inferior)
+    }
+
+    fn from_process(pid: Pid, child: Option<Child>) -> Inferior {
+        Inferior {
+            pid,
+            child,
+            breakpoints: HashMap::new(),
+            watchpoints: [None; NUM_WATCHPOINTS],
+            watchpoint_hit: None,
+            timeout: None,
+            timed_out: false,
+            signal_handling: HashMap::new(),
+            pending_signal: None,
+            load_bias: 0,
+            image: 0..0,
+        }
+    }
+
+    /// Finds where the executable is mapped, for translating addresses (see find_image).
+    fn locate_image(&mut self, target: &str) {
+        if let Some((load_bias, image)) = find_image(target, self.pid) {
+            self.load_bias = load_bias;
+            self.image = image;
         }
-        break_points.clear();
-        inferior.wait(None).ok()?;
-        Some(inferior)
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Whether this inferior was attached to rather than started by the debugger.
+    pub fn is_attached(&self) -> bool {
+        self.child.is_none()
+    }
+
+    /// Describes the process state from /proc/<pid>/stat, e.g. "stopped (tracing stop)".
+    pub fn state(&self) -> Option<String> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", self.pid)).ok()?;
+        // The state follows the command name, which is in parentheses and may contain spaces
+        let state = stat[stat.rfind(')')? + 1..].split_whitespace().next()?;
+        let description = match state {
+            "R" => "running",
+            "S" => "sleeping",
+            "D" => "waiting on disk",
+            "T" => "stopped",
+            "t" => "stopped (tracing stop)",
+            "Z" => "exited (zombie)",
+            "X" => "exited",
+            _ => state,
+        };
+        Some(description.to_string())
+    }
+
+    /// Returns the arguments the process was started with, from /proc/<pid>/cmdline.
+    pub fn cmdline(&self) -> Option<Vec<String>> {
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", self.pid)).ok()?;
+        Some(
+            cmdline
+                .split(|&byte| byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect(),
+        )
+    }
+
+    /// Returns the current instruction pointer of this (stopped) inferior, as a debug info
+    /// address.
+    pub fn instruction_ptr(&self) -> Result<usize, nix::Error> {
+        Ok(self.to_static(ptrace::getregs(self.pid())?.rip as usize))
+    }
+
+    /// Translates a debug info address (what DwarfData and the user's breakpoints use) to the
+    /// address it runs at in this process.
+    pub fn to_runtime(&self, addr: usize) -> usize {
+        addr + self.load_bias
+    }
+
+    /// Translates a run time address back to a debug info address. Addresses outside the
+    /// executable, such as in shared libraries, are returned unchanged.
+    pub fn to_static(&self, addr: usize) -> usize {
+        if self.image.contains(&addr) {
+            addr - self.load_bias
+        } else {
+            addr
+        }
+    }
+
+    /// Limits how long each continue may run; see wait_until.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Sets how each signal the inferior receives while continuing is handled.
+    pub fn set_signal_handling(&mut self, signal_handling: HashMap<signal::Signal, SignalHandling>) {
+        self.signal_handling = signal_handling;
+    }
+
+    /// Whether the last continue stopped because it ran into the timeout.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
     pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
+        self.status_from(waitpid(self.pid(), options)?)
+    }
+
+    /// Waits like wait(None), except that if the inferior is still running at the deadline it
+    /// is stopped with SIGSTOP, and that stop is returned. Without a deadline this just waits.
+    /// The inferior is polled rather than waited on, since waitpid can't time out.
+    fn wait_until(&mut self, deadline: Option<Instant>) -> Result<Status, nix::Error> {
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return self.wait(None),
+        };
+        loop {
+            match waitpid(self.pid(), Some(WaitPidFlag::WNOHANG))? {
+                WaitStatus::StillAlive => {}
+                status => return self.status_from(status),
+            }
+            if Instant::now() >= deadline {
+                signal::kill(self.pid(), signal::Signal::SIGSTOP)?;
+                self.timed_out = true;
+                return self.wait(None);
+            }
+            thread::sleep(TIMEOUT_POLL_INTERVAL);
+        }
+    }
+
+    fn status_from(&self, wait_status: WaitStatus) -> Result<Status, nix::Error> {
+        Ok(match wait_status {
             WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
             WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
             WaitStatus::Stopped(_pid, signal) => {
                 let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+                Status::Stopped(signal, self.to_static(regs.rip as usize))
             }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 
-    pub fn continue_run(&mut self, break_points: &mut Vec<usize>) -> Result<Status, nix::Error> {
-        for break_point in break_points.into_iter() {
-            if let Err(e) = &self.write_byte(*break_point, 0xcc) {
-                println!("Error setting breakpoint: {}", e);
+    /// Writes 0xcc at every breakpoint address that isn't installed yet. Addresses that are
+    /// already installed are skipped, so this is safe to call before every resume. Breakpoints
+    /// are given as debug info addresses; the installed map is keyed by run time address.
+    ///
+    /// Returns the addresses that couldn't be written, such as ones in code that isn't mapped
+    /// yet. They stay pending, and are tried again the next time this is called.
+    pub fn install_breakpoints(&mut self, break_points: &[usize]) -> Vec<usize> {
+        let mut pending = Vec::new();
+        for &addr in break_points {
+            let runtime_addr = self.to_runtime(addr);
+            if self.breakpoints.contains_key(&runtime_addr) {
+                continue;
+            }
+            match self.write_byte(runtime_addr, 0xcc) {
+                Ok(orig_byte) => {
+                    self.breakpoints.insert(runtime_addr, orig_byte);
+                }
+                Err(_) => pending.push(addr),
+            }
+        }
+        pending
+    }
+
+    /// Whether a breakpoint is written into memory at this debug info address.
+    pub fn is_installed(&self, addr: usize) -> bool {
+        self.breakpoints.contains_key(&self.to_runtime(addr))
+    }
+
+    /// Removes the breakpoint at a debug info address, putting the original byte back.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        let runtime_addr = self.to_runtime(addr);
+        if let Some(orig_byte) = self.breakpoints.remove(&runtime_addr) {
+            self.write_byte(runtime_addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
+    pub fn continue_run(&mut self, break_points: &[usize]) -> Result<Status, nix::Error> {
+        self.install_breakpoints(break_points);
+        self.watchpoint_hit = None;
+        self.timed_out = false;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut deliver = self.pending_signal.take();
+        // A signal can stop the step before the instruction has run, leaving the inferior on the
+        // breakpoint, so it's stepped over again until it's past it
+        loop {
+            match self.step_over_breakpoint(deliver.take())? {
+                None => break,
+                Some(status @ Status::Stopped(signal::Signal::SIGTRAP, _)) => {
+                    // A write by the instruction stepped over is reported like any other
+                    self.check_watchpoints()?;
+                    if self.watchpoint_hit.is_some() {
+                        return Ok(status);
+                    }
+                    break;
+                }
+                Some(status @ Status::Stopped(signal, _)) => {
+                    if self.handle_signal(signal, &mut deliver) {
+                        return Ok(status);
+                    }
+                }
+                Some(status) => return Ok(status),
+            }
+        }
+        loop {
+            ptrace::cont(self.pid(), deliver.take())?;
+            let status = self.wait_until(deadline)?;
+            // SIGTRAPs are our own breakpoints, watchpoints and steps, and so is the SIGSTOP of
+            // a timeout, so only other signals are handled as configured
+            if let Status::Stopped(signal, _) = status {
+                if signal != signal::Signal::SIGTRAP && !self.timed_out && !self.handle_signal(signal, &mut deliver) {
+                    continue;
+                }
+            }
+            if let Status::Stopped(signal::Signal::SIGTRAP, _) = status {
+                if let Some(addr) = self.rewind_breakpoint()? {
+                    return Ok(Status::Stopped(signal::Signal::SIGTRAP, self.to_static(addr)));
+                }
+                if self.check_watchpoints()? && self.watchpoint_hit.is_none() {
+                    continue;
+                }
+            }
+            return Ok(status);
+        }
+    }
+
+    /// Applies the handle settings to a stop by a signal other than SIGTRAP. A signal that is
+    /// passed goes into deliver if the inferior is to be resumed straight away, or is kept for
+    /// the next resume otherwise. Returns whether the inferior should stay stopped.
+    fn handle_signal(&mut self, signal: signal::Signal, deliver: &mut Option<signal::Signal>) -> bool {
+        let handling = self.signal_handling.get(&signal).copied().unwrap_or_default();
+        if handling.pass {
+            if handling.stop {
+                self.pending_signal = Some(signal);
+            } else {
+                *deliver = Some(signal);
+            }
+        }
+        handling.stop
+    }
+
+    /// After a SIGTRAP, checks whether the inferior just executed one of our 0xcc bytes, in which
+    /// case rip points one past the breakpoint. If so, moves rip back onto the breakpoint address
+    /// (where the original instruction will run on resume) and returns that run time address.
+    fn rewind_breakpoint(&self) -> Result<Option<usize>, nix::Error> {
+        let mut regs = ptrace::getregs(self.pid())?;
+        let addr = (regs.rip as usize).wrapping_sub(1);
+        if !self.breakpoints.contains_key(&addr) {
+            return Ok(None);
+        }
+        regs.rip = addr as u64;
+        ptrace::setregs(self.pid(), regs)?;
+        Ok(Some(addr))
+    }
+
+    /// If the inferior is stopped on an installed breakpoint, runs the original instruction there
+    /// with a single step, delivering signal if there is one, and then puts the 0xcc back, so
+    /// resuming doesn't trap on the spot again. Returns the status after the step, or None if
+    /// there was no breakpoint to step over.
+    fn step_over_breakpoint(&mut self, signal: Option<signal::Signal>) -> Result<Option<Status>, nix::Error> {
+        let rip = ptrace::getregs(self.pid())?.rip as usize;
+        let orig_byte = match self.breakpoints.get(&rip) {
+            Some(&orig_byte) => orig_byte,
+            None => return Ok(None),
+        };
+        self.write_byte(rip, orig_byte)?;
+        ptrace::step(self.pid(), signal)?;
+        let status = self.wait(None)?;
+        if let Status::Stopped(..) = status {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(Some(status))
+    }
+
+    /// Executes a single instruction, running the original instruction if the inferior is
+    /// stopped on a breakpoint.
+    pub fn step_instruction(&mut self, break_points: &[usize]) -> Result<Status, nix::Error> {
+        self.install_breakpoints(break_points);
+        self.watchpoint_hit = None;
+        self.timed_out = false;
+        let deliver = self.pending_signal.take();
+        let status = match self.step_over_breakpoint(deliver)? {
+            Some(status) => status,
+            None => {
+                ptrace::step(self.pid(), deliver)?;
+                self.wait(None)?
             }
+        };
+        // Otherwise a watchpoint hit by this instruction would go unreported, and its DR6 bit
+        // would be taken for the trigger of the next stop
+        if let Status::Stopped(signal::Signal::SIGTRAP, _) = status {
+            self.check_watchpoints()?;
+        }
+        Ok(status)
+    }
+
+    /// Returns the word at the top of the stack as a debug info address. Right after a call
+    /// instruction, that's the return address.
+    pub fn return_address(&self) -> Result<usize, nix::Error> {
+        let rsp = ptrace::getregs(self.pid())?.rsp;
+        Ok(self.to_static(ptrace::read(self.pid(), rsp as ptrace::AddressType)? as usize))
+    }
+
+    /// Returns the watchpoint that caused the most recent stop, if any.
+    pub fn watchpoint_hit(&self) -> Option<&WatchpointHit> {
+        self.watchpoint_hit.as_ref()
+    }
+
+    /// Watches the 8-byte word at addr for writes using a free hardware debug register. Returns
+    /// the debug register slot used.
+    pub fn set_watchpoint(&mut self, addr: usize) -> Result<usize, String> {
+        if !addr.is_multiple_of(size_of::<u64>()) {
+            return Err(format!("address {:#x} is not 8-byte aligned", addr));
+        }
+        let slot = self
+            .watchpoints
+            .iter()
+            .position(|w| w.is_none())
+            .ok_or("all hardware debug registers are in use")?;
+        let value = ptrace::read(self.pid(), addr as ptrace::AddressType)
+            .map_err(|e| format!("cannot read {:#x}: {}", addr, e))?;
+        // Enable slot locally, break on data writes (R/W = 01), length 8 bytes (LEN = 10)
+        let control_bits = (1 << (2 * slot)) | (0b01 << (16 + 4 * slot)) | (0b10 << (18 + 4 * slot));
+        let result = self.poke_debug_reg(slot, addr as u64).and_then(|_| {
+            let control = self.peek_debug_reg(DR_CONTROL)?;
+            self.poke_debug_reg(DR_CONTROL, control | control_bits)
+        });
+        result.map_err(|e| format!("cannot set debug registers: {}", e))?;
+        self.watchpoints[slot] = Some((addr, value as u64));
+        Ok(slot)
+    }
+
+    /// After a SIGTRAP, reads and clears DR6 and, if a watchpoint fired and the word it watches
+    /// really changed, records the hit in watchpoint_hit. Returns whether a watchpoint fired at
+    /// all, since the debug registers fire on any write, even one storing the same value.
+    fn check_watchpoints(&mut self) -> Result<bool, nix::Error> {
+        let slot = match self.triggered_watchpoint()? {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        let (addr, old_value) = self.watchpoints[slot].unwrap();
+        let new_value = ptrace::read(self.pid(), addr as ptrace::AddressType)? as u64;
+        if new_value != old_value {
+            self.watchpoints[slot] = Some((addr, new_value));
+            self.watchpoint_hit = Some(WatchpointHit { slot, addr, old_value, new_value });
+        }
+        Ok(true)
+    }
+
+    /// Reads and clears DR6, returning the watchpoint slot that triggered the last SIGTRAP.
+    fn triggered_watchpoint(&mut self) -> Result<Option<usize>, nix::Error> {
+        if self.watchpoints.iter().all(|w| w.is_none()) {
+            return Ok(None);
+        }
+        let status = self.peek_debug_reg(DR_STATUS)?;
+        self.poke_debug_reg(DR_STATUS, 0)?;
+        Ok((0..NUM_WATCHPOINTS).find(|&slot| {
+            status & (1 << slot) != 0 && self.watchpoints[slot].is_some()
+        }))
+    }
+
+    fn peek_debug_reg(&self, reg: usize) -> Result<u64, nix::Error> {
+        let offset = DEBUG_REG_OFFSET + reg * size_of::<u64>();
+        // PEEKUSER returns the value directly, so errors can only be told apart via errno
+        let value = unsafe {
+            *libc::__errno_location() = 0;
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                self.pid().as_raw(),
+                offset as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        match nix::errno::errno() {
+            0 => Ok(value as u64),
+            _ => Err(nix::Error::last()),
         }
-        break_points.clear();
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    }
+
+    fn poke_debug_reg(&self, reg: usize, value: u64) -> Result<(), nix::Error> {
+        let offset = DEBUG_REG_OFFSET + reg * size_of::<u64>();
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                self.pid().as_raw(),
+                offset as *mut libc::c_void,
+                value as *mut libc::c_void,
+            )
+        };
+        if ret < 0 {
+            return Err(nix::Error::last());
+        }
+        Ok(())
+    }
+
+    /// Restores the original bytes under all breakpoints, disables watchpoints, and stops
+    /// tracing, leaving the process running on its own.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        let breakpoints: Vec<(usize, u8)> = self.breakpoints.drain().collect();
+        for (addr, orig_byte) in breakpoints {
+            self.write_byte(addr, orig_byte)?;
+        }
+        if self.watchpoints.iter().any(|w| w.is_some()) {
+            self.poke_debug_reg(DR_CONTROL, 0)?;
+            self.watchpoints = [None; NUM_WATCHPOINTS];
+        }
+        ptrace::detach(self.pid(), None)
     }
 
     pub fn kill(&mut self) {
         println!("Killing running inferior (pid {})", self.pid());
-        if let Err(e) = Child::kill(&mut self.child) {
+        let result = match self.child.as_mut() {
+            Some(child) => child.kill().map_err(|e| e.to_string()),
+            None => signal::kill(self.pid(), signal::Signal::SIGKILL).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
             println!("kill process error: {}", e);
+            return;
         }
+        // Reap the killed process so it doesn't linger as a zombie
+        let _ = self.wait(None);
+    }
+
+    /// Walks the saved base pointer chain from the current frame out to main. The innermost
+    /// frame comes first. Instruction pointers are debug info addresses; base pointers are left
+    /// as run time addresses, since they point at the stack.
+    pub fn stack_frames(&self, debug_data: &dwarf_data::DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let mut instruction_ptr = self.to_static(regs.rip as usize);
+        let mut base_ptr = regs.rbp as usize;
+        let mut frames = Vec::new();
+        loop {
+            frames.push(Frame { instruction_ptr, base_ptr });
+            match debug_data.get_function_from_addr(instruction_ptr) {
+                Some(function_name) if function_name != "main" => {}
+                _ => break,
+            }
+            instruction_ptr = self.to_static(ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as usize);
+            base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as usize;
+        }
+        Ok(frames)
     }
 
     pub fn print_backtrace(&self, debug_data: &dwarf_data::DwarfData) -> Result<(), nix::Error> {
-        if let Ok(reg) = ptrace::getregs(self.pid()) {
-            let mut instruction_ptr = reg.rip as usize;
-            let mut base_ptr = reg.rbp as usize;
-            loop {
-                let function_name = debug_data.get_function_from_addr(instruction_ptr).expect("wrong addr");
-                let path_name = debug_data.get_line_from_addr(instruction_ptr).expect("wrong addr");
-                println!("{} {}", function_name, path_name);
-                if function_name == "main" { break; }
-                instruction_ptr = ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as usize;
-                base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as usize;
+        for frame in self.stack_frames(debug_data)? {
+            let function_name = debug_data
+                .get_function_from_addr(frame.instruction_ptr)
+                .unwrap_or_else(|| "??".to_string());
+            let path_name = debug_data
+                .get_nearest_line_from_addr(frame.instruction_ptr)
+                .map_or_else(|| format!("({:#x})", frame.instruction_ptr), |line| line.to_string());
+            match self.format_args(debug_data, &frame) {
+                Some(args) => println!("{} ({}) {}", function_name, args, path_name),
+                None => println!("{} {}", function_name, path_name),
             }
         }
         Ok(())
     }
 
+    /// Returns where a variable of the function running in the given frame lives. Global
+    /// addresses come from the debug info, so they are translated by the load bias.
+    fn variable_address(&self, var: &dwarf_data::Variable, frame: &Frame) -> usize {
+        match var.location {
+            dwarf_data::Location::Address(addr) => self.to_runtime(addr),
+            dwarf_data::Location::FramePointerOffset(_) => var.address(frame.base_ptr),
+        }
+    }
+
+    /// Reads the 8-byte word holding a variable of the function running in the given frame.
+    pub fn read_variable(&self, var: &dwarf_data::Variable, frame: &Frame) -> Result<u64, nix::Error> {
+        let addr = self.variable_address(var, frame);
+        Ok(ptrace::read(self.pid(), addr as ptrace::AddressType)? as u64)
+    }
+
+    /// Overwrites a variable of the function running in the given frame with the given bytes.
+    pub fn write_variable(&mut self, var: &dwarf_data::Variable, frame: &Frame, bytes: &[u8]) -> Result<(), nix::Error> {
+        let addr = self.variable_address(var, frame);
+        self.write_bytes(addr, bytes)?;
+        Ok(())
+    }
+
+    /// Formats the arguments of the function running in the given frame as "a=1, b=2". Returns
+    /// None if there is no argument info or the values can't be read.
+    fn format_args(&self, debug_data: &dwarf_data::DwarfData, frame: &Frame) -> Option<String> {
+        let function = debug_data.get_function_at(frame.instruction_ptr)?;
+        let args = function
+            .variables
+            .iter()
+            .filter(|var| var.is_parameter)
+            .map(|var| {
+                let raw = ptrace::read(self.pid(), var.address(frame.base_ptr) as ptrace::AddressType).ok()?;
+                Some(format!("{}={}", var.name, var.entity_type.format_value(raw as u64)))
+            })
+            .collect::<Option<Vec<String>>>()?;
+        if args.is_empty() {
+            return None;
+        }
+        Some(args.join(", "))
+    }
+
+    /// Reads up to len bytes of the inferior's memory starting at addr (a runtime address), as
+    /// the program itself sees them: installed breakpoints read back as their original bytes.
+    /// Stops early at the first word that can't be read, such as the end of a mapping.
+    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let pid = self.pid();
+        let mut bytes = read_words(addr, len, |aligned_addr| {
+            ptrace::read(pid, aligned_addr as ptrace::AddressType).map(|word| word as u64)
+        })?;
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            if let Some(&orig_byte) = self.breakpoints.get(&(addr + offset)) {
+                *byte = orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
-        let aligned_addr = align_addr_to_word(addr);
-        let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        ptrace::write(
-            self.pid(),
-            aligned_addr as ptrace::AddressType,
-            updated_word as *mut std::ffi::c_void,
-        )?;
-        Ok(orig_byte as u8)
+        Ok(self.write_bytes(addr, &[val])?[0])
+    }
+
+    /// Writes bytes into the inferior's memory starting at addr, which needn't be aligned, and
+    /// returns the bytes they replaced.
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<Vec<u8>, nix::Error> {
+        let pid = self.pid();
+        patch_words(
+            addr,
+            bytes,
+            |aligned_addr| Ok(ptrace::read(pid, aligned_addr as ptrace::AddressType)? as u64),
+            |aligned_addr, word| {
+                ptrace::write(pid, aligned_addr as ptrace::AddressType, word as *mut std::ffi::c_void)
+            },
+        )
     }
 }
 
+/// Patches bytes into memory at addr through read-modify-writes of each aligned word they
+/// touch, since ptrace can only read and write whole words. Returns the original bytes.
+fn patch_words<E>(
+    addr: usize,
+    bytes: &[u8],
+    mut read_word: impl FnMut(usize) -> Result<u64, E>,
+    mut write_word: impl FnMut(usize, u64) -> Result<(), E>,
+) -> Result<Vec<u8>, E> {
+    let mut orig_bytes = Vec::with_capacity(bytes.len());
+    let mut written = 0;
+    while written < bytes.len() {
+        let aligned_addr = align_addr_to_word(addr + written);
+        let mut word = read_word(aligned_addr)?;
+        let mut byte_offset = addr + written - aligned_addr;
+        while byte_offset < size_of::<usize>() && written < bytes.len() {
+            let shift = 8 * byte_offset;
+            orig_bytes.push((word >> shift) as u8);
+            word = (word & !(0xff << shift)) | ((bytes[written] as u64) << shift);
+            byte_offset += 1;
+            written += 1;
+        }
+        write_word(aligned_addr, word)?;
+    }
+    Ok(orig_bytes)
+}
+
+/// Reads len bytes starting at addr, one aligned word at a time. If a word can't be read, returns
+/// the bytes before it, or the error if not even the first byte could be read.
+fn read_words<E>(
+    addr: usize,
+    len: usize,
+    mut read_word: impl FnMut(usize) -> Result<u64, E>,
+) -> Result<Vec<u8>, E> {
+    let mut bytes = Vec::with_capacity(len);
+    while bytes.len() < len {
+        let aligned_addr = align_addr_to_word(addr + bytes.len());
+        let word = match read_word(aligned_addr) {
+            Ok(word) => word,
+            Err(e) if bytes.is_empty() => return Err(e),
+            Err(_) => break,
+        };
+        let byte_offset = addr + bytes.len() - aligned_addr;
+        let count = (size_of::<usize>() - byte_offset).min(len - bytes.len());
+        bytes.extend_from_slice(&word.to_le_bytes()[byte_offset..byte_offset + count]);
+    }
+    Ok(bytes)
+}
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Runs patch_words against a fake memory of words 0x1000, 0x1008 and 0x1010 holding
+    /// 0x00, 0x01, ... 0x17 in order, and returns the original bytes and resulting memory.
+    fn patch(addr: usize, bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let memory = RefCell::new(HashMap::new());
+        for word in 0..3u64 {
+            let value = (0..8).fold(0u64, |acc, byte| acc | ((8 * word + byte) << (8 * byte)));
+            memory.borrow_mut().insert(0x1000 + 8 * word as usize, value);
+        }
+        let orig_bytes = patch_words(
+            addr,
+            bytes,
+            |aligned_addr| memory.borrow().get(&aligned_addr).copied().ok_or(aligned_addr),
+            |aligned_addr, word| {
+                memory.borrow_mut().insert(aligned_addr, word);
+                Ok(())
+            },
+        )
+        .unwrap();
+        let memory = memory.into_inner();
+        let contents = (0..3).flat_map(|word| memory[&(0x1000 + 8 * word)].to_le_bytes().to_vec()).collect();
+        (orig_bytes, contents)
+    }
+
+    #[test]
+    fn test_patch_words_single_byte() {
+        let (orig_bytes, contents) = patch(0x1003, &[0xcc]);
+        assert_eq!(orig_bytes, vec![0x03]);
+        assert_eq!(contents[3], 0xcc);
+        assert!(contents.iter().enumerate().all(|(i, &b)| i == 3 || b == i as u8));
+    }
+
+    #[test]
+    fn test_patch_words_unaligned_multi_word() {
+        // Starts mid-word and spans the whole middle word into the last one
+        let bytes: Vec<u8> = (0xa0..0xac).collect();
+        let (orig_bytes, contents) = patch(0x1006, &bytes);
+        assert_eq!(orig_bytes, (0x06..0x12).collect::<Vec<u8>>());
+        for (i, &b) in contents.iter().enumerate() {
+            let expected = if (6..18).contains(&i) { 0xa0 + (i - 6) as u8 } else { i as u8 };
+            assert_eq!(b, expected, "byte {}", i);
+        }
+    }
+
+    /// Reads from two readable words at 0x1000, where each byte holds its own offset.
+    fn read(addr: usize, len: usize) -> Result<Vec<u8>, usize> {
+        read_words(addr, len, |aligned_addr| {
+            if aligned_addr == 0x1000 || aligned_addr == 0x1008 {
+                let word = aligned_addr as u64 - 0x1000;
+                Ok((0..8).fold(0u64, |acc, byte| acc | ((word + byte) << (8 * byte))))
+            } else {
+                Err(aligned_addr)
+            }
+        })
+    }
+
+    #[test]
+    fn test_read_words_unaligned() {
+        assert_eq!(read(0x1005, 6), Ok((0x05..0x0b).collect()));
+        assert_eq!(read(0x1002, 0), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_read_words_stops_at_unmapped() {
+        assert_eq!(read(0x100c, 16), Ok((0x0c..0x10).collect()));
+        assert_eq!(read(0x1010, 4), Err(0x1010));
+    }
+
+    #[test]
+    fn test_patch_words_empty() {
+        assert_eq!(patch(0x1005, &[]), (vec![], (0..24).collect()));
+    }
+
+    #[test]
+    fn test_patch_words_error() {
+        // The word at 0x1018 isn't mapped, so the write fails part way through
+        let result = patch_words(0x1014, &[1; 8], |addr| if addr < 0x1018 { Ok(0) } else { Err(addr) }, |_, _| Ok(()));
+        assert_eq!(result, Err(0x1018));
+    }
+}
@@ -7,18 +7,50 @@ mod gimli_wrapper;
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
+use std::time::Duration;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
-    }
-    let target = &args[1];
+    let mut args: Vec<String> = env::args().collect();
+    // "--timeout SECONDS" may come anywhere, so take it out before looking at the rest
+    let timeout = match args.iter().position(|arg| arg == "--timeout") {
+        Some(index) => match args.get(index + 1).and_then(|secs| secs.parse::<f64>().ok()) {
+            Some(secs) if secs > 0.0 && secs.is_finite() => {
+                args.drain(index..index + 2);
+                Some(Duration::from_secs_f64(secs))
+            }
+            _ => {
+                println!("Invalid timeout; expected a number of seconds");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // Either "deet <target>" or "deet --pid N [target]"; without a target, read the symbols of
+    // the executable the process is running
+    let (target, pid) = match args.get(1).map(|arg| arg.as_str()) {
+        Some("--pid") if args.len() == 3 || args.len() == 4 => match args[2].parse::<i32>() {
+            Ok(pid) => (args.get(3).cloned().unwrap_or_else(|| format!("/proc/{}/exe", pid)), Some(pid)),
+            Err(_) => {
+                println!("Invalid pid {}", args[2]);
+                std::process::exit(1);
+            }
+        },
+        Some(target) if args.len() == 2 && target != "--pid" => (target.to_string(), None),
+        _ => {
+            println!("Usage: {} [--timeout <seconds>] <target program>", args[0]);
+            println!("       {} [--timeout <seconds>] --pid <pid> [target program]", args[0]);
+            std::process::exit(1);
+        }
+    };
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(&target);
+    debugger.set_timeout(timeout);
+    if let Some(pid) = pid {
+        debugger.attach(pid);
+    }
+    debugger.run();
 }
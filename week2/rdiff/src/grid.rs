@@ -1,20 +1,23 @@
+use std::fmt::Display;
+
 // Grid implemented as flat vector
-pub struct Grid {
+pub struct Grid<T> {
     num_rows: usize,
     num_cols: usize,
-    elems: Vec<usize>,
+    elems: Vec<T>,
 }
 
-impl Grid {
-    /// Returns a Grid of the specified size, with all elements pre-initialized to zero.
-    pub fn new(num_rows: usize, num_cols: usize) -> Grid {
+impl<T: Clone + Default> Grid<T> {
+    /// Returns a Grid of the specified size, with all elements pre-initialized to T's default
+    /// (zero, for numbers).
+    pub fn new(num_rows: usize, num_cols: usize) -> Grid<T> {
         Grid {
-            num_rows: num_rows,
-            num_cols: num_cols,
-            // This syntax uses the vec! macro to create a vector of zeros, initialized to a
-            // specific length
+            num_rows,
+            num_cols,
+            // This syntax uses the vec! macro to create a vector of default values, initialized
+            // to a specific length
             // https://stackoverflow.com/a/29530932
-            elems: vec![0; num_rows * num_cols],
+            elems: vec![T::default(); num_rows * num_cols],
         }
     }
 
@@ -30,29 +33,56 @@ impl Grid {
     /// but others argue that makes code needlessly complex. Here, we decided to return Option to
     /// give you more practice with Option :) and because this similar library returns Option:
     /// https://docs.rs/array2d/0.2.1/array2d/struct.Array2D.html
-    pub fn get(&self, row: usize, col: usize) -> Option<usize> {
-        if row < self.num_rows && col <= self.num_cols {
-            return Some(self.elems[row * self.num_cols + col])
+    pub fn get(&self, row: usize, col: usize) -> Option<T> {
+        if row < self.num_rows && col < self.num_cols {
+            Some(self.elems[row * self.num_cols + col].clone())
         }
         else {
-            return None
+            None
         }
     }
 
     /// Sets the element at the specified location to the specified value. If the location is out
     /// of bounds, returns Err with an error message.
-    pub fn set(&mut self, row: usize, col: usize, val: usize) -> Result<(), &'static str> {
-        if row < self.num_rows && col <= self.num_cols {
+    pub fn set(&mut self, row: usize, col: usize, val: T) -> Result<(), &'static str> {
+        if row < self.num_rows && col < self.num_cols {
             self.elems[row * self.num_cols + col] = val;
-            return Ok(())
+            Ok(())
         }
         else {
-            return Err("out of bound")
+            Err("out of bound")
         }
     }
 
+    /// Iterates over the elements of the specified row, left to right. If the row is out of
+    /// bounds, the iterator is empty.
+    pub fn iter_row(&self, row: usize) -> impl Iterator<Item = &T> {
+        let row = if row < self.num_rows {
+            &self.elems[row * self.num_cols..(row + 1) * self.num_cols]
+        } else {
+            &[]
+        };
+        row.iter()
+    }
+
+    /// Iterates over the elements of the specified column, top to bottom. If the column is out
+    /// of bounds, the iterator is empty.
+    pub fn iter_col(&self, col: usize) -> impl Iterator<Item = &T> {
+        let col = if col < self.num_cols { &self.elems[col..] } else { &[] };
+        col.iter().step_by(self.num_cols.max(1))
+    }
+
+    /// Iterates over every element along with its (row, col) location, a row at a time.
+    pub fn cells(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let num_cols = self.num_cols;
+        self.elems.iter().enumerate().map(move |(i, elem)| ((i / num_cols, i % num_cols), elem))
+    }
+
     /// Prints a visual representation of the grid. You can use this for debugging.
-    pub fn display(&self) {
+    pub fn display(&self)
+    where
+        T: Display,
+    {
         for row in 0..self.num_rows {
             let mut line = String::new();
             for col in 0..self.num_cols {
@@ -62,10 +92,10 @@ impl Grid {
         }
     }
 
-    /// Resets all the elements to zero.
+    /// Resets all the elements to T's default.
     pub fn clear(&mut self) {
         for i in self.elems.iter_mut() {
-            *i = 0;
+            *i = T::default();
         }
     }
 }
@@ -104,5 +134,41 @@ mod test {
                 assert_eq!(grid.get(r, c).unwrap(), r * n_cols + c);
             }
         }
+        assert_eq!(grid.get(0, n_cols), None);
+        assert!(grid.set(n_rows, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_grid_iterators() {
+        let mut grid = Grid::new(3, 4);
+        for r in 0..3 {
+            for c in 0..4 {
+                grid.set(r, c, r * 10 + c).unwrap();
+            }
+        }
+        let manual: usize = (0..4).map(|c| grid.get(1, c).unwrap()).sum();
+        assert_eq!(grid.iter_row(1).sum::<usize>(), manual);
+        assert_eq!(grid.iter_col(2).cloned().collect::<Vec<_>>(), vec![2, 12, 22]);
+        assert_eq!(grid.iter_row(3).count(), 0);
+        assert_eq!(grid.iter_col(4).count(), 0);
+
+        let cells: Vec<_> = grid.cells().collect();
+        assert_eq!(cells.len(), 12);
+        assert_eq!(cells[5], ((1, 1), &11));
+        assert!(grid.cells().all(|((r, c), &val)| grid.get(r, c) == Some(val)));
+    }
+
+    #[test]
+    fn test_grid_of_other_types() {
+        let mut scores: Grid<f64> = Grid::new(2, 2);
+        scores.set(1, 0, 0.5).unwrap();
+        assert_eq!(scores.get(1, 0), Some(0.5));
+        assert_eq!(scores.get(0, 1), Some(0.0));
+
+        let mut names: Grid<String> = Grid::new(1, 2);
+        names.set(0, 1, String::from("kept")).unwrap();
+        assert_eq!(names.get(0, 1).as_deref(), Some("kept"));
+        names.clear();
+        assert_eq!(names.get(0, 1).as_deref(), Some(""));
     }
 }
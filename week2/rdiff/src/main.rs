@@ -1,30 +1,400 @@
+#[cfg(feature = "normalize-unicode")]
+extern crate unicode_normalization;
+
 use grid::Grid; // For lcs()
 use std::env;
 use std::fs::File; // For read_file_lines()
-use std::io::{self, BufRead}; // For read_file_lines()
+use std::io::{self, BufRead, Write}; // For read_file_lines()
+use std::mem;
+use std::ops::Range; // For unified_hunks()
 use std::process;
 use std::cmp;
+use std::collections::{HashMap, HashSet}; // For the N-way report
+use std::sync::atomic::{AtomicUsize, Ordering}; // For lcs_parallel()
+use std::sync::Barrier;
+use std::thread;
+#[cfg(feature = "normalize-unicode")]
+use unicode_normalization::UnicodeNormalization; // For comparison_key()
 
 pub mod grid;
 
 /// Reads the file at the supplied path, and returns a vector of strings.
+#[cfg(test)]
 fn read_file_lines(filename: &String) -> Result<Vec<String>, io::Error> {
-    let file = File::open(filename).expect("File open error!");
-    let mut res: Vec<String> = vec![];
-    for line in io::BufReader::new(file).lines() {
-        let line_str = line?;
-        res.push(line_str);
+    Ok(read_file(filename)?.lines)
+}
+
+/// The lines of an input file, along with whether its last line lacked a terminating newline.
+struct Input {
+    lines: Vec<String>,
+    /// The lines as they are compared, when options make that differ from how they are shown.
+    keys: Option<Vec<String>>,
+    missing_newline: bool,
+}
+
+impl Input {
+    /// The lines to compare: the comparison keys if there are any, otherwise the lines as shown.
+    fn keys(&self) -> &Vec<String> {
+        self.keys.as_ref().unwrap_or(&self.lines)
+    }
+
+    /// Whether the line at index (0-based) is a last line with no newline after it.
+    fn unterminated(&self, index: usize) -> bool {
+        self.missing_newline && index + 1 == self.lines.len()
+    }
+}
+
+/// Iterates over the lines of a reader, like BufRead::lines, but splits on raw bytes so that it
+/// can also tell whether the last line was terminated by a newline. Lets the modes that don't
+/// need the LCS table look at a file without holding all of it in memory.
+struct LineReader<R> {
+    reader: R,
+    /// Set once a final line with no newline after it has been read.
+    missing_newline: bool,
+}
+
+impl LineReader<Box<dyn BufRead>> {
+    /// Opens the file at the supplied path, or standard input if the path is "-".
+    fn open(filename: &String) -> io::Result<LineReader<Box<dyn BufRead>>> {
+        let reader: Box<dyn BufRead> = if filename == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(io::BufReader::new(File::open(filename)?))
+        };
+        Ok(LineReader::new(reader))
+    }
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(reader: R) -> LineReader<R> {
+        LineReader {reader, missing_newline: false}
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = io::Result<String>;
+    fn next(&mut self) -> Option<io::Result<String>> {
+        let mut buf = Vec::new();
+        match self.reader.read_until(b'\n', &mut buf) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(err) => return Some(Err(err)),
+        }
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+            // Strip a CRLF ending too, as BufRead::lines does
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        } else {
+            self.missing_newline = true;
+        }
+        Some(String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)))
+    }
+}
+
+/// Reads the whole file at the supplied path, or standard input if it is "-".
+fn read_file(filename: &String) -> Result<Input, io::Error> {
+    read_lines(LineReader::open(filename)?)
+}
+
+/// Describes why a file couldn't be read: the system's message without its "(os error N)"
+/// suffix, so that a missing file and an unreadable one are told apart in plain words.
+fn describe_read_error(err: &io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::NotFound => String::from("No such file or directory"),
+        io::ErrorKind::PermissionDenied => String::from("Permission denied"),
+        _ => {
+            let message = err.to_string();
+            match message.find(" (os error") {
+                Some(end) => message[..end].to_string(),
+                None => message,
+            }
+        }
+    }
+}
+
+/// Unwraps the result of reading a file, or reports why it couldn't be read and exits with
+/// status 2, diff's status for trouble.
+fn or_exit<T>(result: io::Result<T>, filename: &str) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("rdiff: cannot read {}: {}", filename, describe_read_error(&err));
+        process::exit(2);
+    })
+}
+
+/// Reads every remaining line from a reader.
+fn read_lines<R: BufRead>(mut reader: LineReader<R>) -> Result<Input, io::Error> {
+    let lines = reader.by_ref().collect::<Result<Vec<String>, io::Error>>()?;
+    Ok(Input {lines, keys: None, missing_newline: reader.missing_newline})
+}
+
+/// Command-line options controlling how the diff is printed.
+#[derive(Default)]
+struct Options {
+    /// Print only the changed lines, prefixed with their line numbers.
+    changes_only: bool,
+    /// Break ties in the LCS backtrace towards fewer change blocks (see prefer_insertion).
+    minimal: bool,
+    /// Expand tabs to spaces with tab stops this many columns apart before comparing.
+    expand_tabs: Option<usize>,
+    /// Print only how similar the files are, as a percentage.
+    similarity: bool,
+    /// Print only whether the files differ, not how.
+    brief: bool,
+    /// Say so when the files are identical, instead of printing nothing of note.
+    report_identical: bool,
+    /// Ignore whitespace at the end of lines when comparing them.
+    ignore_trailing_space: bool,
+    /// Ignore case when comparing lines, under Unicode case folding.
+    ignore_case: bool,
+    /// Compare lines in Unicode Normalization Form C, so that canonically equivalent text, such
+    /// as a precomposed "é" and an "e" followed by a combining accent, compares equal.
+    #[cfg(feature = "normalize-unicode")]
+    normalize_unicode: bool,
+    /// Ignore changes in whitespace when comparing lines: leading and trailing whitespace is
+    /// dropped, and every run of it inside a line counts as a single space.
+    ignore_whitespace: bool,
+    /// Compare lines by only this whitespace-separated field (1-based) rather than in full.
+    key_field: Option<usize>,
+    /// Names to show for the two files in headers and messages, in place of their paths.
+    labels: Vec<String>,
+    /// Show each changed region as one merged text with the changed words colored.
+    color_words: bool,
+    /// Fill the LCS table with this many threads instead of one.
+    parallel: Option<usize>,
+    /// Print the two files in columns next to each other, this many characters wide.
+    side_by_side: Option<usize>,
+    /// In side-by-side output, leave out the rows where both sides are the same.
+    suppress_common_lines: bool,
+    /// Diff the second file against the first, as if they had been given the other way round.
+    reverse: bool,
+    /// Print a unified diff, with this many lines of context around each change.
+    unified: Option<usize>,
+    /// Compare the files as multisets of lines, ignoring order.
+    set: bool,
+    /// Print only how many lines were inserted and deleted.
+    stat: bool,
+    /// Diff between lines unique to both files first (see anchored_edits) instead of running one
+    /// LCS over everything. Never turned on by itself: large inputs already get the plain diff in
+    /// linear space from lcs_hirschberg, and only this flag trades that diff for speed.
+    speed_large_files: bool,
+}
+
+/// Splits the command-line arguments into options and the remaining positional arguments.
+fn parse_args(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    // Only takes effect with -y, which may come after it
+    let mut width_option = None;
+    let mut context_option = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--changes-only" => options.changes_only = true,
+            "--minimal" => options.minimal = true,
+            "--color-words" => options.color_words = true,
+            "--similarity" => options.similarity = true,
+            "--set" => options.set = true,
+            "-y" | "--side-by-side" => options.side_by_side = Some(SIDE_BY_SIDE_WIDTH),
+            "--suppress-common-lines" => options.suppress_common_lines = true,
+            "--width" => match args.next().map(|width| width.parse()) {
+                Some(Ok(width)) if width > 0 => width_option = Some(width),
+                Some(_) => return Err(String::from("invalid width")),
+                None => return Err(String::from("option --width requires an argument")),
+            },
+            flag if flag.starts_with("--width=") => match flag["--width=".len()..].parse() {
+                Ok(width) if width > 0 => width_option = Some(width),
+                _ => return Err(String::from("invalid width")),
+            },
+            "--speed-large-files" => options.speed_large_files = true,
+            "--stat" => options.stat = true,
+            "-q" | "--brief" => options.brief = true,
+            "-s" | "--report-identical-files" => options.report_identical = true,
+            "-Z" | "--ignore-trailing-space" => options.ignore_trailing_space = true,
+            "-i" | "--ignore-case" => options.ignore_case = true,
+            #[cfg(feature = "normalize-unicode")]
+            "--normalize-unicode" => options.normalize_unicode = true,
+            "-w" | "--ignore-whitespace" => options.ignore_whitespace = true,
+            "-R" | "--reverse" => options.reverse = true,
+            "-u" | "--unified" => options.unified = Some(UNIFIED_CONTEXT),
+            "-c" | "--context" => match args.next().map(|context| context.parse()) {
+                Some(Ok(context)) => context_option = Some(context),
+                Some(Err(_)) => return Err(String::from("invalid context length")),
+                None => return Err(format!("option {} requires an argument", arg)),
+            },
+            flag if flag.starts_with("--context=") => match flag["--context=".len()..].parse() {
+                Ok(context) => context_option = Some(context),
+                Err(_) => return Err(String::from("invalid context length")),
+            },
+            flag if flag.starts_with("--key-field=") => {
+                let field = &flag["--key-field=".len()..];
+                match field.parse() {
+                    Ok(field) if field > 0 => options.key_field = Some(field),
+                    _ => return Err(format!("invalid key field {}", field)),
+                }
+            }
+            "--expand-tabs" => options.expand_tabs = Some(8),
+            flag if flag.starts_with("--expand-tabs=") => {
+                let size = &flag["--expand-tabs=".len()..];
+                match size.parse() {
+                    Ok(size) if size > 0 => options.expand_tabs = Some(size),
+                    _ => return Err(format!("invalid tab size {}", size)),
+                }
+            }
+            "--parallel" => {
+                options.parallel = Some(thread::available_parallelism().map_or(1, |threads| threads.get()))
+            }
+            flag if flag.starts_with("--parallel=") => {
+                let threads = &flag["--parallel=".len()..];
+                match threads.parse() {
+                    Ok(threads) if threads > 0 => options.parallel = Some(threads),
+                    _ => return Err(format!("invalid thread count {}", threads)),
+                }
+            }
+            "--label" => match args.next() {
+                Some(label) => options.labels.push(label.clone()),
+                None => return Err(String::from("option --label requires an argument")),
+            },
+            flag if flag.starts_with("--label=") => options.labels.push(flag["--label=".len()..].to_string()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if options.labels.len() > 2 {
+        return Err(String::from("too many file label options"));
+    }
+    // Standard input can only be read through once
+    if positional.iter().filter(|filename| *filename == "-").count() > 1 {
+        return Err(String::from("standard input can only be given as one of the files"));
+    }
+    if let (Some(width), Some(_)) = (width_option, options.side_by_side) {
+        options.side_by_side = Some(width);
+    }
+    // Context lines only exist in unified output, so asking for them asks for that
+    if context_option.is_some() {
+        options.unified = context_option;
     }
-    Ok(res)
+    Ok((options, positional))
 }
 
-fn lcs(seq1: &Vec<String>, seq2: &Vec<String>) -> Grid {
+/// Replaces each tab with spaces up to the next multiple of `tabsize` columns.
+fn expand_tabs(line: &str, tabsize: usize) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let width = tabsize - column % tabsize;
+            expanded.push_str(&" ".repeat(width));
+            column += width;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// Applies the line transformations requested in the options to one line of input.
+fn transform_line(options: &Options, line: String) -> String {
+    match options.expand_tabs {
+        Some(tabsize) => expand_tabs(&line, tabsize),
+        None => line,
+    }
+}
+
+/// Whether the options compare lines by something other than their displayed text.
+fn uses_comparison_keys(options: &Options) -> bool {
+    #[cfg(feature = "normalize-unicode")]
+    {
+        if options.normalize_unicode {
+            return true;
+        }
+    }
+    options.ignore_trailing_space || options.ignore_case || options.ignore_whitespace || options.key_field.is_some()
+}
+
+/// For --key-field: the given whitespace-separated field (1-based) of a line, or None if the line
+/// has fewer fields than that.
+fn key_field(line: &str, field: usize) -> Option<&str> {
+    line.split_whitespace().nth(field - 1)
+}
+
+/// Case-folds text so that strings differing only in case compare equal. Going through upper
+/// case first folds what lowercasing alone misses: "ß" and "SS" both become "ss", and final
+/// "ς" becomes "σ" like the other sigmas.
+fn fold_case(text: &str) -> String {
+    text.chars().flat_map(char::to_uppercase).flat_map(char::to_lowercase).collect()
+}
+
+/// Returns the form of a (transformed) line that is actually compared. Unlike transform_line,
+/// this never changes what is printed: the diff still shows the original line.
+fn comparison_key(options: &Options, line: &str) -> String {
+    let mut key = line;
+    if let Some(field) = options.key_field {
+        // A line without the field compares as if the field were empty
+        key = key_field(key, field).unwrap_or("");
+    }
+    if options.ignore_trailing_space {
+        key = key.trim_end();
+    }
+    let collapsed;
+    if options.ignore_whitespace {
+        collapsed = key.split_whitespace().collect::<Vec<_>>().join(" ");
+        key = &collapsed;
+    }
+    // Before case folding, which needs canonically equivalent text to be encoded alike
+    #[cfg(feature = "normalize-unicode")]
+    let normalized;
+    #[cfg(feature = "normalize-unicode")]
+    if options.normalize_unicode {
+        normalized = key.nfc().collect::<String>();
+        key = &normalized;
+    }
+    if options.ignore_case {
+        return fold_case(key);
+    }
+    key.to_string()
+}
+
+/// Reads a file and applies the line transformations requested in the options.
+fn read_input(options: &Options, filename: &String) -> Result<Input, io::Error> {
+    let input = prepare_input(options, read_file(filename)?);
+    if let Some(field) = options.key_field {
+        let missing = input.lines.iter().filter(|line| key_field(line, field).is_none()).count();
+        if missing > 0 {
+            eprintln!("rdiff: {} lines of {} have no field {}; comparing them as empty", missing, filename, field);
+        }
+    }
+    Ok(input)
+}
+
+/// Applies the line transformations requested in the options to the lines of an input, and works
+/// out the keys they are compared by.
+fn prepare_input(options: &Options, mut input: Input) -> Input {
+    input.lines = input.lines.into_iter().map(|line| transform_line(options, line)).collect();
+    if uses_comparison_keys(options) || input.missing_newline {
+        let mut keys: Vec<String> = input.lines.iter().map(|line| comparison_key(options, line)).collect();
+        // An unterminated last line must not match a terminated one, or the diff couldn't say
+        // which file the missing newline belongs to. No line read from a file contains a
+        // newline, so one at the end of the key makes it differ from every other key.
+        if input.missing_newline {
+            keys.last_mut().unwrap().push('\n');
+        }
+        input.keys = Some(keys);
+    }
+    input
+}
+
+// Takes &Vec rather than a slice so callers can pass `&iter.collect()`
+#[allow(clippy::ptr_arg)]
+fn lcs(seq1: &Vec<String>, seq2: &Vec<String>) -> Grid<usize> {
     let len1 = seq1.len();
     let len2 = seq2.len();
     let mut dp = Grid::new(len1 + 1, len2 + 1);
-    for i in 0..len1 {
-        for j in 0..len2 {
-            if seq1[i] == seq2[j] {
+    for (i, line1) in seq1.iter().enumerate() {
+        for (j, line2) in seq2.iter().enumerate() {
+            if line1 == line2 {
                 dp.set(i + 1, j + 1, dp.get(i, j).unwrap() + 1).unwrap();
             }
             else {
@@ -35,37 +405,984 @@ fn lcs(seq1: &Vec<String>, seq2: &Vec<String>) -> Grid {
     dp
 }
 
-fn print_diff(lcs_table: &Grid, lines1: &Vec<String>, lines2: &Vec<String>, i: usize, j: usize) {
-    if i > 0 && j > 0 && lines1[i - 1] == lines2[j - 1] {
-        print_diff(lcs_table, lines1, lines2, i - 1, j - 1);
-        println!("  {}", lines1[i - 1]);
+/// Computes the same table as lcs, using num_threads threads. Each cell depends only on the cells
+/// above and to its left, so all the cells on one anti-diagonal (where i + j is the same) can be
+/// filled at once. The threads split every anti-diagonal between them and wait for each other
+/// before moving on to the next. That is a lot of waiting for small inputs, so this only pays off
+/// for large ones.
+fn lcs_parallel(seq1: &[String], seq2: &[String], num_threads: usize) -> Grid<usize> {
+    let (len1, len2) = (seq1.len(), seq2.len());
+    let cols = len2 + 1;
+    // Threads write disjoint cells of each diagonal; the barrier orders them before any reads
+    let cells: Vec<AtomicUsize> = (0..(len1 + 1) * cols).map(|_| AtomicUsize::new(0)).collect();
+    let barrier = Barrier::new(num_threads);
+    thread::scope(|scope| {
+        for worker in 0..num_threads {
+            let (cells, barrier) = (&cells, &barrier);
+            scope.spawn(move || {
+                let get = |i: usize, j: usize| cells[i * cols + j].load(Ordering::Relaxed);
+                for diagonal in 2..=len1 + len2 {
+                    // Rows on this diagonal that have a cell inside the table, excluding row and
+                    // column 0, which stay 0
+                    let first = cmp::max(1, diagonal.saturating_sub(len2));
+                    let last = cmp::min(len1, diagonal - 1);
+                    if first <= last {
+                        let share = (last - first + 1).div_ceil(num_threads);
+                        let start = first + worker * share;
+                        let end = cmp::min(last + 1, start + share);
+                        for i in start..end {
+                            let j = diagonal - i;
+                            let value = if seq1[i - 1] == seq2[j - 1] {
+                                get(i - 1, j - 1) + 1
+                            } else {
+                                cmp::max(get(i, j - 1), get(i - 1, j))
+                            };
+                            cells[i * cols + j].store(value, Ordering::Relaxed);
+                        }
+                    }
+                    barrier.wait();
+                }
+            });
+        }
+    });
+    let mut dp = Grid::new(len1 + 1, cols);
+    for (index, cell) in cells.into_iter().enumerate() {
+        dp.set(index / cols, index % cols, cell.into_inner()).unwrap();
+    }
+    dp
+}
+
+/// Returns just the length of the longest common subsequence. Only two rows of the LCS table are
+/// kept at a time, so this needs O(len2) memory instead of the full grid, and seq1 is only walked
+/// once, so it can be streamed.
+fn lcs_length<S: AsRef<str>>(seq1: impl IntoIterator<Item = S>, seq2: &[String]) -> usize {
+    let mut prev = vec![0; seq2.len() + 1];
+    let mut curr = vec![0; seq2.len() + 1];
+    for line1 in seq1 {
+        for (j, line2) in seq2.iter().enumerate() {
+            curr[j + 1] = if line1.as_ref() == line2 {
+                prev[j] + 1
+            } else {
+                cmp::max(curr[j], prev[j + 1])
+            };
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[seq2.len()]
+}
+
+/// Returns 2 * LCS / (len1 + len2) as a whole percentage, rounded down so that only identical
+/// files report 100. Two empty files count as identical.
+fn similarity<S: AsRef<str>>(seq1: impl IntoIterator<Item = S>, seq2: &[String]) -> usize {
+    let mut len1 = 0;
+    let lcs = lcs_length(seq1.into_iter().inspect(|_| len1 += 1), seq2);
+    let total = len1 + seq2.len();
+    if total == 0 {
+        return 100;
+    }
+    2 * lcs * 100 / total
+}
+
+/// For --minimal: computes, for every prefix pair (i, j), the fewest blocks of consecutive
+/// changes an optimal diff of that prefix can have, not counting a block it ends with (that block
+/// merges with a change that follows it). Only moves that keep the diff optimal are considered,
+/// and equal lines are always matched, exactly as edit_script walks the table.
+fn change_blocks(lcs_table: &Grid<usize>, lines1: &[String], lines2: &[String]) -> Grid<usize> {
+    let (rows, cols) = lcs_table.size();
+    // open[i][j]: fewest blocks before a trailing change; closed[i][j]: fewest blocks in total
+    let mut open = Grid::new(rows, cols);
+    let mut closed = Grid::new(rows, cols);
+    for i in 0..rows {
+        for j in 0..cols {
+            if i == 0 && j == 0 {
+                continue;
+            }
+            if i > 0 && j > 0 && lines1[i - 1] == lines2[j - 1] {
+                let blocks = closed.get(i - 1, j - 1).unwrap();
+                open.set(i, j, blocks).unwrap();
+                closed.set(i, j, blocks).unwrap();
+                continue;
+            }
+            let here = lcs_table.get(i, j).unwrap();
+            let mut best = usize::MAX;
+            if j > 0 && lcs_table.get(i, j - 1).unwrap() == here {
+                best = cmp::min(best, open.get(i, j - 1).unwrap());
+            }
+            if i > 0 && lcs_table.get(i - 1, j).unwrap() == here {
+                best = cmp::min(best, open.get(i - 1, j).unwrap());
+            }
+            open.set(i, j, best).unwrap();
+            closed.set(i, j, best + 1).unwrap();
+        }
+    }
+    open
+}
+
+/// Decides whether the backtrace at (i, j), where the lines differ, should emit an insertion of
+/// line j rather than a deletion of line i. Both are valid when the neighbouring LCS values tie.
+/// By default ties go to the insertion. With --minimal (`blocks` is the change_blocks table),
+/// ties go to whichever branch leaves fewer separate blocks of changes, still preferring the
+/// insertion when both are equally good. This keeps changes grouped together, closer to what
+/// GNU diff emits.
+fn prefer_insertion(lcs_table: &Grid<usize>, blocks: Option<&Grid<usize>>, i: usize, j: usize) -> bool {
+    let insertion = lcs_table.get(i, j - 1).unwrap();
+    let deletion = lcs_table.get(i - 1, j).unwrap();
+    match blocks {
+        Some(blocks) if insertion == deletion => {
+            blocks.get(i, j - 1).unwrap() <= blocks.get(i - 1, j).unwrap()
+        }
+        _ => insertion_wins(insertion, deletion),
+    }
+}
+
+/// The default tie-break, given the LCS values either move leads to: the insertion, unless the
+/// deletion keeps a longer common subsequence. Shared with lcs_hirschberg, whose diff has to match.
+fn insertion_wins(insertion: usize, deletion: usize) -> bool {
+    insertion >= deletion
+}
+
+/// Whether two files have the same contents (as compared under the options),
+/// including whether each ends with a newline. Both are read a line at a time and only up to
+/// the first difference, which is much cheaper than building the LCS table.
+fn identical<R1: BufRead, R2: BufRead>(options: &Options, mut lines1: LineReader<R1>, mut lines2: LineReader<R2>) -> io::Result<bool> {
+    loop {
+        match (lines1.next().transpose()?, lines2.next().transpose()?) {
+            (Some(line1), Some(line2)) => {
+                let line1 = transform_line(options, line1);
+                let line2 = transform_line(options, line2);
+                if comparison_key(options, &line1) != comparison_key(options, &line2) {
+                    return Ok(false);
+                }
+            }
+            (None, None) => return Ok(lines1.missing_newline == lines2.missing_newline),
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Writes the lines of a diff. A line that was the unterminated last line of an input is followed
+/// by GNU diff's "\\ No newline at end of file" marker rather than passed off as ending in a
+/// newline, so the diff records byte for byte how each input ended.
+struct DiffWriter<W> {
+    out: W,
+}
+
+impl<W: Write> DiffWriter<W> {
+    fn new(out: W) -> DiffWriter<W> {
+        DiffWriter {out}
+    }
+
+    /// Writes one line of the diff: the prefix, then the line itself.
+    fn line(&mut self, prefix: &str, line: &str, unterminated: bool) -> io::Result<()> {
+        writeln!(self.out, "{}{}", prefix, line)?;
+        if unterminated {
+            writeln!(self.out, "\\ No newline at end of file")?;
+        }
+        Ok(())
+    }
+
+    fn blank(&mut self) -> io::Result<()> {
+        writeln!(self.out)
+    }
+}
+
+/// Prints the diff of input1 against input2 from their LCS table: the edit script edit_script
+/// reads off it, as print_edits prints it. Returns whether any line was inserted or deleted.
+fn print_diff<W: Write>(out: &mut DiffWriter<W>, options: &Options, lcs_table: &Grid<usize>, blocks: Option<&Grid<usize>>, input1: &Input, input2: &Input) -> io::Result<bool> {
+    let edits = edit_script(lcs_table, blocks, input1.keys(), input2.keys());
+    print_edits(out, options, &edits, input1, input2)?;
+    Ok(edits.iter().any(|edit| !matches!(edit, Edit::Keep(..))))
+}
+
+/// One step of an edit script read off an LCS table, as 0-based indices: an element kept from
+/// both sequences, an element of the second sequence inserted, or one of the first deleted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Keep(usize, usize),
+    Insert(usize),
+    Delete(usize),
+}
+
+/// Walks the LCS table back from the end and returns the edit script in forward order. This is
+/// the one backtrace of the table that print_diff and the other output formats share, and it
+/// loops rather than recursing, so any length works.
+fn edit_script(lcs_table: &Grid<usize>, blocks: Option<&Grid<usize>>, seq1: &[String], seq2: &[String]) -> Vec<Edit> {
+    let (mut i, mut j) = (seq1.len(), seq2.len());
+    let mut edits = Vec::new();
+    loop {
+        if i > 0 && j > 0 && seq1[i - 1] == seq2[j - 1] {
+            i -= 1;
+            j -= 1;
+            edits.push(Edit::Keep(i, j));
+        } else if j > 0 && (i == 0 || prefer_insertion(lcs_table, blocks, i, j)) {
+            j -= 1;
+            edits.push(Edit::Insert(j));
+        } else if i > 0 {
+            i -= 1;
+            edits.push(Edit::Delete(i));
+        } else {
+            break;
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+/// One step of a diff, with the text of the line it concerns rather than its position.
+#[derive(Debug, PartialEq)]
+enum DiffOp<'a> {
+    Equal(&'a str),
+    Insert(&'a str),
+    Delete(&'a str),
+}
+
+/// Returns the diff of lines1 against lines2 that an edit script describes, as data: each step
+/// carrying its line, in order. print_edits prints exactly these steps, so tests can check what
+/// the diff is without scraping the printed output.
+fn compute_diff<'a>(edits: &[Edit], lines1: &'a [String], lines2: &'a [String]) -> Vec<DiffOp<'a>> {
+    edits
+        .iter()
+        .map(|edit| match *edit {
+            Edit::Keep(i, _) => DiffOp::Equal(lines1[i].as_str()),
+            Edit::Insert(j) => DiffOp::Insert(lines2[j].as_str()),
+            Edit::Delete(i) => DiffOp::Delete(lines1[i].as_str()),
+        })
+        .collect()
+}
+
+/// Sub-problems of lcs_hirschberg with at most this many table cells are backtraced from a
+/// full table, which is much faster than splitting them further.
+const HIRSCHBERG_BASE_CELLS: usize = 1 << 12;
+
+/// Returns the same edit script as the default edit_script over the lcs table, in memory linear
+/// in the shorter sequence rather than the product of the two, by Hirschberg's divide and conquer.
+///
+/// The usual Hirschberg split picks any middle point of any longest common subsequence, so it
+/// would not make the same choices between equally long ones, and the diff would change. Instead
+/// this finds where edit_script's own backtrace crosses the middle row: the backtrace's moves
+/// along a row depend only on that row and the one above, so each row maps the column the
+/// backtrace enters it at to the column it leaves it from, and composing those maps from the
+/// bottom row up gives the crossing. Both halves then have corners on the backtrace, where LCS
+/// values counted from the half's own corner lead to the same choices as the full table's.
+fn lcs_hirschberg(seq1: &[String], seq2: &[String]) -> Vec<Edit> {
+    hirschberg_edits(seq1, seq2, HIRSCHBERG_BASE_CELLS)
+}
+
+fn hirschberg_edits(seq1: &[String], seq2: &[String], base_cells: usize) -> Vec<Edit> {
+    // Rows are kept along the shorter sequence, so the table is swept the other way round when
+    // seq1 is the shorter one
+    let transposed = seq1.len() < seq2.len();
+    let (rows, cols) = if transposed { (seq2, seq1) } else { (seq1, seq2) };
+    let mut hirschberg = Hirschberg { rows, cols, transposed, base_cells, edits: Vec::new() };
+    hirschberg.solve(0..rows.len(), 0..cols.len());
+    hirschberg.edits
+}
+
+/// A move of the backtrace through lcs_hirschberg's table, from whichever way round it is swept.
+#[derive(Clone, Copy)]
+enum Step {
+    Diagonal,
+    Left,
+    Up,
+}
+
+struct Hirschberg<'a> {
+    rows: &'a [String],
+    cols: &'a [String],
+    transposed: bool,
+    base_cells: usize,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Hirschberg<'a> {
+    /// Appends the edits for the part of the table between two cells on the backtrace, the
+    /// corners of rows and cols.
+    fn solve(&mut self, rows: Range<usize>, cols: Range<usize>) {
+        let (n, m) = (rows.len(), cols.len());
+        if n <= 1 || m == 0 || (n + 1) * (m + 1) <= self.base_cells {
+            return self.solve_with_table(rows, cols);
+        }
+        let mid = n / 2;
+        let mut prev = vec![0; m + 1];
+        let mut curr = vec![0; m + 1];
+        // crossing[x]: the column the backtrace reaches row mid at if it enters the current row
+        // at column x. leaves[x]: the column it moves up from when it enters the current row at x
+        let mut crossing: Vec<usize> = (0..=m).collect();
+        let mut leaves = vec![0; m + 1];
+        for i in 1..=n {
+            self.fill_row(&rows, &cols, i, &prev, &mut curr);
+            if i > mid {
+                for j in 0..=m {
+                    leaves[j] = match self.step(&rows, &cols, i, j, &prev, &curr) {
+                        Step::Diagonal => j - 1,
+                        Step::Up => j,
+                        Step::Left => leaves[j - 1],
+                    };
+                }
+                for leave in leaves.iter_mut() {
+                    *leave = crossing[*leave];
+                }
+                mem::swap(&mut crossing, &mut leaves);
+            }
+            mem::swap(&mut prev, &mut curr);
+        }
+        let split = (rows.start + mid, cols.start + crossing[m]);
+        self.solve(rows.start..split.0, cols.start..split.1);
+        self.solve(split.0..rows.end, split.1..cols.end);
+    }
+
+    /// Backtraces the part of the table between two cells on the backtrace from a full table.
+    fn solve_with_table(&mut self, rows: Range<usize>, cols: Range<usize>) {
+        let width = cols.len() + 1;
+        let mut table = vec![0; (rows.len() + 1) * width];
+        for i in 1..=rows.len() {
+            let (above, row) = table[(i - 1) * width..(i + 1) * width].split_at_mut(width);
+            self.fill_row(&rows, &cols, i, above, row);
+        }
+        let (mut i, mut j) = (rows.len(), cols.len());
+        let start = self.edits.len();
+        while i > 0 || j > 0 {
+            let row = &table[i * width..(i + 1) * width];
+            let above = if i > 0 { &table[(i - 1) * width..i * width] } else { row };
+            let step = self.step(&rows, &cols, i, j, above, row);
+            let (row, col) = (rows.start + i, cols.start + j);
+            self.edits.push(match (step, self.transposed) {
+                (Step::Diagonal, false) => Edit::Keep(row - 1, col - 1),
+                (Step::Diagonal, true) => Edit::Keep(col - 1, row - 1),
+                (Step::Left, false) => Edit::Insert(col - 1),
+                (Step::Up, true) => Edit::Insert(row - 1),
+                (Step::Up, false) => Edit::Delete(row - 1),
+                (Step::Left, true) => Edit::Delete(col - 1),
+            });
+            match step {
+                Step::Diagonal => {
+                    i -= 1;
+                    j -= 1;
+                }
+                Step::Left => j -= 1,
+                Step::Up => i -= 1,
+            }
+        }
+        self.edits[start..].reverse();
+    }
+
+    /// Fills in row i of the LCS table of rows against cols, given the row above.
+    fn fill_row(&self, rows: &Range<usize>, cols: &Range<usize>, i: usize, above: &[usize], row: &mut [usize]) {
+        let line = &self.rows[rows.start + i - 1];
+        for (j, other) in self.cols[cols.clone()].iter().enumerate() {
+            row[j + 1] = if line == other {
+                above[j] + 1
+            } else {
+                cmp::max(row[j], above[j + 1])
+            };
+        }
+    }
+
+    /// The move edit_script makes from cell (i, j), other than (0, 0), given rows i and i - 1 of
+    /// the table (the same row when i is 0). The insertion is a move left, or up when the table
+    /// is transposed.
+    fn step(&self, rows: &Range<usize>, cols: &Range<usize>, i: usize, j: usize, above: &[usize], row: &[usize]) -> Step {
+        if i > 0 && j > 0 && self.rows[rows.start + i - 1] == self.cols[cols.start + j - 1] {
+            Step::Diagonal
+        } else if self.transposed {
+            if i > 0 && (j == 0 || insertion_wins(above[j], row[j - 1])) { Step::Up } else { Step::Left }
+        } else if j > 0 && (i == 0 || insertion_wins(row[j - 1], above[j])) {
+            Step::Left
+        } else {
+            Step::Up
+        }
+    }
+}
+
+/// Beyond this many LCS table cells, the diff is worked out by lcs_hirschberg instead of from a
+/// full table, unless --minimal or --parallel need the table. This used to turn on
+/// --speed-large-files instead, but that changes the diff, and lcs_hirschberg doesn't.
+const LARGE_FILE_CELLS: usize = 1 << 26;
+
+/// For --speed-large-files: builds an edit script around anchors, the lines that appear exactly
+/// once in each file, in the spirit of patience diff. The longest run of anchors that appear in
+/// the same order in both files is kept, and only the gaps between them get a full LCS. This is
+/// much faster on large, mostly similar files, and tends to line up changes with the structure
+/// of the text, such as whole functions. The result is not always a longest common subsequence,
+/// so it can differ from the plain diff, usually for the better, which is why it is only used
+/// when asked for, however large the inputs.
+fn anchored_edits(seq1: &[String], seq2: &[String]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    push_anchored_edits(seq1, seq2, 0, 0, &mut edits);
+    edits
+}
+
+/// Appends the edits for seq1 against seq2, which start at offset1 and offset2 of the inputs.
+fn push_anchored_edits(seq1: &[String], seq2: &[String], offset1: usize, offset2: usize, edits: &mut Vec<Edit>) {
+    // Equal lines at either end are kept as they are; that also makes new anchors show up in
+    // the gaps between the outer anchors
+    let prefix = seq1.iter().zip(seq2).take_while(|(line1, line2)| line1 == line2).count();
+    let suffix = seq1[prefix..].iter().rev().zip(seq2[prefix..].iter().rev())
+        .take_while(|(line1, line2)| line1 == line2)
+        .count();
+    edits.extend((0..prefix).map(|k| Edit::Keep(offset1 + k, offset2 + k)));
+    let middle1 = &seq1[prefix..seq1.len() - suffix];
+    let middle2 = &seq2[prefix..seq2.len() - suffix];
+    let (offset1_mid, offset2_mid) = (offset1 + prefix, offset2 + prefix);
+
+    let anchors = unique_anchors(middle1, middle2);
+    if anchors.is_empty() {
+        if middle1.is_empty() || middle2.is_empty() {
+            edits.extend((0..middle1.len()).map(|i| Edit::Delete(offset1_mid + i)));
+            edits.extend((0..middle2.len()).map(|j| Edit::Insert(offset2_mid + j)));
+        } else {
+            let lcs_table = lcs(&middle1.to_vec(), &middle2.to_vec());
+            edits.extend(edit_script(&lcs_table, None, middle1, middle2).into_iter().map(|edit| match edit {
+                Edit::Keep(i, j) => Edit::Keep(offset1_mid + i, offset2_mid + j),
+                Edit::Insert(j) => Edit::Insert(offset2_mid + j),
+                Edit::Delete(i) => Edit::Delete(offset1_mid + i),
+            }));
+        }
+    } else {
+        let (mut start1, mut start2) = (0, 0);
+        for (i, j) in anchors {
+            push_anchored_edits(&middle1[start1..i], &middle2[start2..j], offset1_mid + start1, offset2_mid + start2, edits);
+            edits.push(Edit::Keep(offset1_mid + i, offset2_mid + j));
+            start1 = i + 1;
+            start2 = j + 1;
+        }
+        push_anchored_edits(&middle1[start1..], &middle2[start2..], offset1_mid + start1, offset2_mid + start2, edits);
+    }
+
+    let (end1, end2) = (offset1 + seq1.len(), offset2 + seq2.len());
+    edits.extend((0..suffix).rev().map(|k| Edit::Keep(end1 - 1 - k, end2 - 1 - k)));
+}
+
+/// Returns the positions (i, j) of lines that appear exactly once in both sequences, trimmed to
+/// the longest run whose positions increase in both, in increasing order.
+fn unique_anchors(seq1: &[String], seq2: &[String]) -> Vec<(usize, usize)> {
+    // For each line: how often it appears in each sequence, and where it last appeared
+    let mut seen: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, line) in seq1.iter().enumerate() {
+        let entry = seen.entry(line.as_str()).or_insert((0, 0, 0, 0));
+        entry.0 += 1;
+        entry.2 = i;
+    }
+    for (j, line) in seq2.iter().enumerate() {
+        if let Some(entry) = seen.get_mut(line.as_str()) {
+            entry.1 += 1;
+            entry.3 = j;
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = seen
+        .values()
+        .filter(|&&(count1, count2, _, _)| count1 == 1 && count2 == 1)
+        .map(|&(_, _, i, j)| (i, j))
+        .collect();
+    pairs.sort();
+
+    // Longest increasing subsequence of the j's, by patience sorting: tails[k] is the pair that
+    // ends the best run of length k + 1 found so far, and previous links each pair to the one
+    // before it in its run
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (index, &(_, j)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].1 < j);
+        previous[index] = if length > 0 { Some(tails[length - 1]) } else { None };
+        if length == tails.len() {
+            tails.push(index);
+        } else {
+            tails[length] = index;
+        }
+    }
+    let mut anchors = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        anchors.push(pairs[index]);
+        next = previous[index];
+    }
+    anchors.reverse();
+    anchors
+}
+
+/// Prints an edit script in rdiff's own format: a blank line, then every line, prefixed by "  "
+/// if kept, "> " if inserted or "< " if deleted. With --changes-only, just the changed lines,
+/// each with its line number.
+fn print_edits<W: Write>(out: &mut DiffWriter<W>, options: &Options, edits: &[Edit], input1: &Input, input2: &Input) -> io::Result<()> {
+    if !options.changes_only {
+        out.blank()?;
+    }
+    // The steps cover every line of both inputs in order, so counting them gives each line's index
+    let (mut i, mut j) = (0, 0);
+    for op in compute_diff(edits, &input1.lines, &input2.lines) {
+        match op {
+            DiffOp::Equal(line) => {
+                if !options.changes_only {
+                    out.line("  ", line, input1.unterminated(i))?;
+                }
+                i += 1;
+                j += 1;
+            }
+            DiffOp::Insert(line) => {
+                let prefix = if options.changes_only { format!("> {}: ", j + 1) } else { String::from("> ") };
+                out.line(&prefix, line, input2.unterminated(j))?;
+                j += 1;
+            }
+            DiffOp::Delete(line) => {
+                let prefix = if options.changes_only { format!("< {}: ", i + 1) } else { String::from("< ") };
+                out.line(&prefix, line, input1.unterminated(i))?;
+                i += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// For --stat: prints how many lines the edits insert and delete, as a summary for the second
+/// file. These are the lines print_diff shows with > and < respectively.
+fn print_stat<W: Write>(out: &mut DiffWriter<W>, name: &str, edits: &[Edit]) -> io::Result<()> {
+    let insertions = edits.iter().filter(|edit| matches!(edit, Edit::Insert(_))).count();
+    let deletions = edits.iter().filter(|edit| matches!(edit, Edit::Delete(_))).count();
+    let summary = format!("{} insertions(+), {} deletions(-)", insertions, deletions);
+    out.line(&format!("{}: ", name), &summary, false)
+}
+
+/// Lines of context shown around each change in -u output.
+const UNIFIED_CONTEXT: usize = 3;
+
+/// For -u: groups an edit script into hunks, each a range of edits covering a run of changes
+/// plus up to `context` kept lines either side. Changes with at most 2 * context kept lines
+/// between them share a hunk, so that no line of context is printed twice.
+fn unified_hunks(edits: &[Edit], context: usize) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    // The hunk being built: where it starts, and the last change in it
+    let mut current: Option<(usize, usize)> = None;
+    for (k, edit) in edits.iter().enumerate() {
+        if let Edit::Keep(..) = edit {
+            continue;
+        }
+        current = match current {
+            Some((start, last)) if k - last - 1 <= 2 * context => Some((start, k)),
+            Some((start, last)) => {
+                hunks.push(start..cmp::min(edits.len(), last + 1 + context));
+                Some((k.saturating_sub(context), k))
+            }
+            None => Some((k.saturating_sub(context), k)),
+        };
+    }
+    if let Some((start, last)) = current {
+        hunks.push(start..cmp::min(edits.len(), last + 1 + context));
+    }
+    hunks
+}
+
+/// Formats one side of a unified hunk header: the first line number and the number of lines,
+/// which is left out when it is 1. An empty side gives the line before it, as GNU diff does.
+fn unified_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Prints the edit script as a unified diff, which patch can apply: the "---" and "+++" file
+/// headers, then each hunk with its "@@" header and its lines prefixed by " ", "-" or "+".
+/// Prints nothing at all if there are no changes.
+fn print_unified<W: Write>(out: &mut DiffWriter<W>, options: &Options, names: (&str, &str), edits: &[Edit], input1: &Input, input2: &Input) -> io::Result<()> {
+    let hunks = unified_hunks(edits, options.unified.unwrap_or(UNIFIED_CONTEXT));
+    if hunks.is_empty() {
+        return Ok(());
+    }
+    out.line("--- ", names.0, false)?;
+    out.line("+++ ", names.1, false)?;
+    let old_lines = |edits: &[Edit]| edits.iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+    let new_lines = |edits: &[Edit]| edits.iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+    for hunk in hunks {
+        // The edits cover every line in order, so those before the hunk give where it starts
+        let (start1, start2) = (old_lines(&edits[..hunk.start]), new_lines(&edits[..hunk.start]));
+        let edits = &edits[hunk];
+        let (count1, count2) = (old_lines(edits), new_lines(edits));
+        let header = format!("@@ -{} +{} @@", unified_range(start1, count1), unified_range(start2, count2));
+        out.line("", &header, false)?;
+        for edit in edits {
+            match *edit {
+                Edit::Keep(i, _) => out.line(" ", &input1.lines[i], input1.unterminated(i))?,
+                Edit::Delete(i) => out.line("-", &input1.lines[i], input1.unterminated(i))?,
+                Edit::Insert(j) => out.line("+", &input2.lines[j], input2.unterminated(j))?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Width of each column of -y output when --width isn't given.
+const SIDE_BY_SIDE_WIDTH: usize = 60;
+
+/// Lines up an edit script as the rows of side-by-side output: the line shown on the left, if
+/// any, the gutter marker, and the line shown on the right. Within each run of changes the
+/// deleted and inserted lines are paired up in order as changed rows ('|'); the rest are shown
+/// alone, on the left ('<') or the right ('>'). Kept lines get a blank marker.
+fn side_by_side_rows(edits: &[Edit]) -> Vec<(Option<usize>, char, Option<usize>)> {
+    let mut rows = Vec::with_capacity(edits.len());
+    let mut start = 0;
+    while start < edits.len() {
+        if let Edit::Keep(i, j) = edits[start] {
+            rows.push((Some(i), ' ', Some(j)));
+            start += 1;
+            continue;
+        }
+        let end = edits[start..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Keep(..)))
+            .map_or(edits.len(), |offset| start + offset);
+        let deleted: Vec<usize> = edits[start..end].iter().filter_map(|edit| match *edit {
+            Edit::Delete(i) => Some(i),
+            _ => None,
+        }).collect();
+        let inserted: Vec<usize> = edits[start..end].iter().filter_map(|edit| match *edit {
+            Edit::Insert(j) => Some(j),
+            _ => None,
+        }).collect();
+        for k in 0..cmp::max(deleted.len(), inserted.len()) {
+            rows.push(match (deleted.get(k), inserted.get(k)) {
+                (Some(&i), Some(&j)) => (Some(i), '|', Some(j)),
+                (Some(&i), None) => (Some(i), '<', None),
+                (None, Some(&j)) => (None, '>', Some(j)),
+                (None, None) => unreachable!(),
+            });
+        }
+        start = end;
+    }
+    rows
+}
+
+/// For -y: prints the edit script as two columns of the given width, cutting longer lines
+/// short, with the gutter marker between them.
+fn print_side_by_side<W: Write>(out: &mut DiffWriter<W>, options: &Options, width: usize, edits: &[Edit], input1: &Input, input2: &Input) -> io::Result<()> {
+    for (left, marker, right) in side_by_side_rows(edits) {
+        if options.suppress_common_lines && marker == ' ' {
+            continue;
+        }
+        let cell = |line: &String| line.chars().take(width).collect::<String>();
+        let left = left.map_or(String::new(), |i| cell(&input1.lines[i]));
+        let right = right.map_or(String::new(), |j| cell(&input2.lines[j]));
+        let row = format!("{:<width$} {} {}", left, marker, right, width = width);
+        out.line("", row.trim_end(), false)?;
+    }
+    Ok(())
+}
+
+/// Beyond this many word pairs, --color-words shows a changed region as a plain line diff
+/// instead, since the word-level LCS table grows with the product of the two word counts.
+const COLOR_WORDS_MAX_CELLS: usize = 1 << 20;
+
+/// Splits text into alternating runs of whitespace and non-whitespace, so that the words
+/// concatenated back together give the original text.
+fn split_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut last_was_space = None;
+    for c in text.chars() {
+        let is_space = c.is_whitespace();
+        if last_was_space == Some(is_space) {
+            words.last_mut().unwrap().push(c);
+        } else {
+            words.push(c.to_string());
+        }
+        last_was_space = Some(is_space);
+    }
+    words
+}
+
+/// Merges the old and new text of a changed region into the new text with the removed words
+/// colored red and the added words green. Line breaks follow the new text: a removed line break
+/// is shown as a space.
+fn color_words(old: &str, new: &str) -> String {
+    let (words1, words2) = (split_words(old), split_words(new));
+    let lcs_table = lcs(&words1, &words2);
+    // Consecutive words with the same coloring are grouped into one run before painting
+    let mut runs: Vec<(String, Option<&str>)> = Vec::new();
+    for edit in edit_script(&lcs_table, None, &words1, &words2) {
+        let (word, sgr) = match edit {
+            Edit::Keep(i, _) => (words1[i].clone(), None),
+            Edit::Delete(i) => (words1[i].replace('\n', " "), Some("31")),
+            Edit::Insert(j) => (words2[j].clone(), Some("32")),
+        };
+        match runs.last_mut() {
+            Some((run, run_sgr)) if *run_sgr == sgr => run.push_str(&word),
+            _ => runs.push((word, sgr)),
+        }
+    }
+    let mut merged = String::new();
+    for (run, sgr) in runs {
+        // Color each line's part separately so that no escape sequence spans a line break
+        for (k, piece) in run.split('\n').enumerate() {
+            if k > 0 {
+                merged.push('\n');
+            }
+            match sgr {
+                Some(sgr) if !piece.is_empty() => merged.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr, piece)),
+                _ => merged.push_str(piece),
+            }
+        }
+    }
+    merged
+}
+
+/// For --color-words: prints the edit script like print_edits, except that each run of changed
+/// lines is printed as the merged text from color_words. Regions too large for a word-level LCS
+/// fall back to the usual "<" and ">" lines.
+fn print_color_words<W: Write>(out: &mut DiffWriter<W>, options: &Options, edits: &[Edit], input1: &Input, input2: &Input) -> io::Result<()> {
+    let (lines1, lines2) = (&input1.lines, &input2.lines);
+    let mut start = 0;
+    while start < edits.len() {
+        if let Edit::Keep(i, _) = edits[start] {
+            if !options.changes_only {
+                out.line("  ", &lines1[i], input1.unterminated(i))?;
+            }
+            start += 1;
+            continue;
+        }
+        let end = edits[start..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Keep(..)))
+            .map_or(edits.len(), |offset| start + offset);
+        let region = &edits[start..end];
+        let deleted: Vec<&str> = region.iter().filter_map(|edit| match *edit {
+            Edit::Delete(i) => Some(lines1[i].as_str()),
+            _ => None,
+        }).collect();
+        let inserted: Vec<&str> = region.iter().filter_map(|edit| match *edit {
+            Edit::Insert(j) => Some(lines2[j].as_str()),
+            _ => None,
+        }).collect();
+        let (old, new) = (deleted.join("\n"), inserted.join("\n"));
+        if split_words(&old).len() * split_words(&new).len() <= COLOR_WORDS_MAX_CELLS {
+            // The merged text ends where both sides end, so it carries either side's marker
+            let unterminated = region.iter().any(|edit| match *edit {
+                Edit::Delete(i) => input1.unterminated(i),
+                Edit::Insert(j) => input2.unterminated(j),
+                Edit::Keep(..) => false,
+            });
+            let merged = color_words(&old, &new);
+            let count = merged.split('\n').count();
+            for (k, line) in merged.split('\n').enumerate() {
+                out.line("  ", line, unterminated && k + 1 == count)?;
+            }
+        } else {
+            for edit in region {
+                match *edit {
+                    Edit::Delete(i) => out.line("< ", &lines1[i], input1.unterminated(i))?,
+                    Edit::Insert(j) => out.line("> ", &lines2[j], input2.unterminated(j))?,
+                    Edit::Keep(..) => unreachable!(),
+                }
+            }
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// For --set: treats each sequence as a multiset of lines and returns the indices of the lines
+/// left over on each side once every line is matched with an equal one on the other side,
+/// regardless of where either appears. A line that appears more often on one side is left over
+/// as many extra times, counting from its first appearance.
+fn set_difference(seq1: &[String], seq2: &[String]) -> (Vec<usize>, Vec<usize>) {
+    let mut counts1: HashMap<&str, usize> = HashMap::new();
+    let mut counts2: HashMap<&str, usize> = HashMap::new();
+    for line in seq1 {
+        *counts1.entry(line.as_str()).or_insert(0) += 1;
+    }
+    for line in seq2 {
+        *counts2.entry(line.as_str()).or_insert(0) += 1;
+    }
+    let left_over = |seq: &[String], counts: &HashMap<&str, usize>, other: &HashMap<&str, usize>| {
+        let mut surplus: HashMap<&str, usize> = counts
+            .iter()
+            .map(|(&line, &count)| (line, count.saturating_sub(other.get(line).copied().unwrap_or(0))))
+            .collect();
+        (0..seq.len())
+            .filter(|&index| match surplus.get_mut(seq[index].as_str()) {
+                Some(remaining) if *remaining > 0 => {
+                    *remaining -= 1;
+                    true
+                }
+                _ => false,
+            })
+            .collect()
+    };
+    (left_over(seq1, &counts1, &counts2), left_over(seq2, &counts2, &counts1))
+}
+
+/// Prints the --set report: the lines only in the first file, then those only in the second.
+fn print_set_diff<W: Write>(out: &mut DiffWriter<W>, options: &Options, input1: &Input, input2: &Input) -> io::Result<bool> {
+    let (only1, only2) = set_difference(input1.keys(), input2.keys());
+    let differ = !only1.is_empty() || !only2.is_empty();
+    for i in only1 {
+        let prefix = if options.changes_only { format!("< {}: ", i + 1) } else { String::from("< ") };
+        out.line(&prefix, &input1.lines[i], input1.unterminated(i))?;
+    }
+    for j in only2 {
+        let prefix = if options.changes_only { format!("> {}: ", j + 1) } else { String::from("> ") };
+        out.line(&prefix, &input2.lines[j], input2.unterminated(j))?;
+    }
+    Ok(differ)
+}
+
+/// Compares any number of files line by line, ignoring order. Returns the distinct lines present
+/// in every file (in the order they first appear in the first file), and for each file the
+/// distinct lines that appear in no other file.
+///
+/// This is a set comparison rather than an N-way LCS: it runs in time linear in the total number
+/// of lines, whereas a true N-way LCS table would need the product of all the file lengths.
+fn common_and_unique(files: &[Vec<String>]) -> (Vec<String>, Vec<Vec<String>>) {
+    // For every distinct line, the number of files it appears in
+    let mut file_counts: HashMap<&str, usize> = HashMap::new();
+    for lines in files {
+        let distinct: HashSet<&str> = lines.iter().map(|line| line.as_str()).collect();
+        for line in distinct {
+            *file_counts.entry(line).or_insert(0) += 1;
+        }
     }
-    else if j > 0 && (i == 0 || lcs_table.get(i, j - 1).unwrap() >= lcs_table.get(i - 1, j).unwrap()) {
-        print_diff(lcs_table, lines1, lines2, i, j - 1);
-        println!("> {}", lines2[j - 1]);
+    let distinct_with_count = |lines: &Vec<String>, count: usize| -> Vec<String> {
+        let mut seen = HashSet::new();
+        lines
+            .iter()
+            .filter(|line| file_counts[line.as_str()] == count && seen.insert(line.as_str()))
+            .cloned()
+            .collect()
+    };
+    let common = files.first().map_or(Vec::new(), |lines| distinct_with_count(lines, files.len()));
+    let unique = files.iter().map(|lines| distinct_with_count(lines, 1)).collect();
+    (common, unique)
+}
+
+/// Prints the N-way report used when more than two files are given. Returns whether any line is
+/// unique to one of the files, which is what makes them differ for the exit status.
+fn print_common_lines(filenames: &[String], files: &[Vec<String>]) -> bool {
+    let (common, unique) = common_and_unique(files);
+    let differ = unique.iter().any(|lines| !lines.is_empty());
+    println!("Common to all {} files:", files.len());
+    for line in common {
+        println!("  {}", line);
     }
-    else if i > 0 && (j == 0 || lcs_table.get(i - 1, j).unwrap() >= lcs_table.get(i, j - 1).unwrap()) {
-        print_diff(lcs_table, lines1, lines2, i - 1, j);
-        println!("< {}", lines1[i - 1]);
+    for (filename, lines) in filenames.iter().zip(unique) {
+        println!("Only in {}:", filename);
+        for line in lines {
+            println!("  {}", line);
+        }
     }
-    else {
-        println!("");
+    differ
+}
+
+/// Returns the two files to diff, old first, and what to call them when printing; the comparison
+/// itself always uses the real paths. With -R the files swap roles, and labels follow the files
+/// they were given for.
+fn file_roles<'a>(options: &'a Options, files: &'a [String]) -> ((&'a String, &'a String), (&'a String, &'a String)) {
+    let (mut filename1, mut filename2) = (&files[0], &files[1]);
+    let mut name1 = options.labels.first().unwrap_or(filename1);
+    let mut name2 = options.labels.get(1).unwrap_or(filename2);
+    if options.reverse {
+        mem::swap(&mut filename1, &mut filename2);
+        mem::swap(&mut name1, &mut name2);
     }
+    ((filename1, filename2), (name1, name2))
 }
 
+// Exits as GNU diff does: 0 if the files are the same, 1 if they differ and 2 if there was
+// trouble, such as bad arguments or an unreadable file.
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        println!("Too few arguments.");
-        process::exit(1);
+    let (options, files) = match parse_args(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("rdiff: {}", message);
+            process::exit(2);
+        }
+    };
+    if files.len() < 2 {
+        eprintln!("Too few arguments.");
+        process::exit(2);
     }
-    let filename1 = &args[1];
-    let filename2 = &args[2];
+    if files.len() > 2 {
+        let contents: Vec<Vec<String>> = files
+            .iter()
+            .map(|filename| or_exit(read_input(&options, filename), filename).lines)
+            .collect();
+        exit_with_difference(print_common_lines(&files, &contents));
+    }
+    let ((filename1, filename2), (name1, name2)) = file_roles(&options, &files);
+
+    if options.similarity {
+        // Only the second file needs to be held in memory; the first is streamed past it
+        let file2 = or_exit(read_input(&options, filename2), filename2);
+        let lines1 = or_exit(LineReader::open(filename1), filename1)
+            .map(|line| comparison_key(&options, &transform_line(&options, or_exit(line, filename1))));
+        println!("{}%", similarity(lines1, file2.keys()));
+        return;
+    }
+    if options.brief || options.report_identical {
+        let lines1 = or_exit(LineReader::open(filename1), filename1);
+        let lines2 = or_exit(LineReader::open(filename2), filename2);
+        let same = identical(&options, lines1, lines2).unwrap_or_else(|err| {
+            eprintln!("rdiff: cannot compare {} and {}: {}", filename1, filename2, describe_read_error(&err));
+            process::exit(2);
+        });
+        if same {
+            if options.report_identical {
+                println!("Files {} and {} are identical", name1, name2);
+            }
+            return;
+        }
+        if options.brief {
+            println!("Files {} and {} differ", name1, name2);
+            process::exit(1);
+        }
+    }
+    let file1 = or_exit(read_input(&options, filename1), filename1);
+    let file2 = or_exit(read_input(&options, filename2), filename2);
+    let stdout = io::stdout();
+    let mut out = DiffWriter::new(stdout.lock());
+    if options.set {
+        let differ = print_set_diff(&mut out, &options, &file1, &file2).expect("Write error!");
+        exit_with_difference(differ);
+    }
+    let cells = (file1.lines.len() + 1).saturating_mul(file2.lines.len() + 1);
+    let edits = if options.speed_large_files {
+        anchored_edits(file1.keys(), file2.keys())
+    } else if cells > LARGE_FILE_CELLS && !options.minimal && options.parallel.is_none() {
+        lcs_hirschberg(file1.keys(), file2.keys())
+    } else {
+        let lcs_table = match options.parallel {
+            Some(threads) => lcs_parallel(file1.keys(), file2.keys(), threads),
+            None => lcs(file1.keys(), file2.keys()),
+        };
+        let blocks = if options.minimal {
+            Some(change_blocks(&lcs_table, file1.keys(), file2.keys()))
+        } else {
+            None
+        };
+        if !options.stat && !options.color_words && options.side_by_side.is_none() && options.unified.is_none() {
+            let differ = print_diff(&mut out, &options, &lcs_table, blocks.as_ref(), &file1, &file2).expect("Write error!");
+            exit_with_difference(differ);
+        }
+        edit_script(&lcs_table, blocks.as_ref(), file1.keys(), file2.keys())
+    };
+    let result = if options.stat {
+        print_stat(&mut out, name2, &edits)
+    } else if options.unified.is_some() {
+        print_unified(&mut out, &options, (name1, name2), &edits, &file1, &file2)
+    } else if let Some(width) = options.side_by_side {
+        print_side_by_side(&mut out, &options, width, &edits, &file1, &file2)
+    } else if options.color_words {
+        print_color_words(&mut out, &options, &edits, &file1, &file2)
+    } else {
+        print_edits(&mut out, &options, &edits, &file1, &file2)
+    };
+    result.expect("Write error!");
+    exit_with_difference(edits.iter().any(|edit| !matches!(edit, Edit::Keep(..))));
+}
 
-    let file1 = read_file_lines(&filename1).expect("Invalid filename1!");
-    let file2 = read_file_lines(&filename2).expect("Invalid filename2!");
-    let lcs_table = lcs(&file1, &file2);
-    print_diff(&lcs_table, &file1, &file2, file1.len(), file2.len());
+/// Exits with status 1 if the files differ, or 0 if they are the same (see main).
+fn exit_with_difference(differ: bool) -> ! {
+    // process::exit doesn't flush stdout on the way out
+    io::stdout().flush().expect("Write error!");
+    process::exit(if differ { 1 } else { 0 })
 }
 
 #[cfg(test)]
@@ -84,6 +1401,16 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_read_errors() {
+        let err = read_file_lines(&String::from("no-such-file.txt")).unwrap_err();
+        assert_eq!(describe_read_error(&err), "No such file or directory");
+        let err = read_file_lines(&String::from("src")).unwrap_err();
+        assert_eq!(describe_read_error(&err), "Is a directory");
+        let err = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(describe_read_error(&err), "Permission denied");
+    }
+
     #[test]
     fn test_lcs() {
         let mut expected = Grid::new(5, 4);
@@ -115,4 +1442,535 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_lcs_parallel() {
+        // Pseudo-random lines from a small alphabet, so there are plenty of matches
+        let mut seed: u32 = 12345;
+        let mut lines = |count: usize| -> Vec<String> {
+            (0..count).map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                ((b'a' + (seed >> 16) as u8 % 6) as char).to_string()
+            }).collect()
+        };
+        let (seq1, seq2) = (lines(300), lines(257));
+        let cases = [(&seq1[..], &seq2[..]), (&seq1[..40], &seq2[..]), (&seq1[..], &seq2[..0]), (&seq1[..0], &seq2[..0])];
+        for (a, b) in cases.iter() {
+            let expected = lcs(&a.to_vec(), &b.to_vec());
+            for &threads in [1, 3, 8].iter() {
+                let result = lcs_parallel(a, b, threads);
+                assert_eq!(result.size(), expected.size());
+                for row in 0..expected.size().0 {
+                    for col in 0..expected.size().1 {
+                        assert_eq!(result.get(row, col), expected.get(row, col), "{} threads", threads);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lcs_hirschberg() {
+        let mut seed: u32 = 54321;
+        let mut lines = |count: usize, alphabet: u8| -> Vec<String> {
+            (0..count).map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                ((b'a' + (seed >> 16) as u8 % alphabet) as char).to_string()
+            }).collect()
+        };
+        let (seq1, seq2, seq3) = (lines(200, 3), lines(173, 3), lines(90, 8));
+        let cases = [(&seq1[..], &seq2[..]), (&seq2[..], &seq1[..]), (&seq1[..], &seq3[..]), (&seq3[..], &seq1[..]),
+                     (&seq1[..7], &seq2[..]), (&seq1[..1], &seq2[..]), (&seq1[..], &seq2[..0]), (&seq1[..0], &seq2[..0])];
+        for (a, b) in cases.iter() {
+            let expected = edit_script(&lcs(&a.to_vec(), &b.to_vec()), None, a, b);
+            // Splitting all the way down, as well as with the usual base case
+            assert_eq!(hirschberg_edits(a, b, 0), expected);
+            assert_eq!(lcs_hirschberg(a, b), expected);
+        }
+    }
+
+    #[test]
+    fn test_common_and_unique() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let files = vec![to_lines("abcda"), to_lines("bcae"), to_lines("cabf")];
+        let (common, unique) = common_and_unique(&files);
+        assert_eq!(common, to_lines("abc"));
+        assert_eq!(unique, vec![to_lines("d"), to_lines("e"), to_lines("f")]);
+    }
+
+    #[test]
+    fn test_set_difference() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        // Order doesn't matter, but how many times a line appears does
+        assert_eq!(set_difference(&to_lines("abc"), &to_lines("cab")), (vec![], vec![]));
+        assert_eq!(set_difference(&to_lines("abca"), &to_lines("cbd")), (vec![0, 3], vec![2]));
+        assert_eq!(set_difference(&to_lines("aab"), &to_lines("baaa")), (vec![], vec![1]));
+        assert_eq!(set_difference(&to_lines(""), &to_lines("ab")), (vec![], vec![0, 1]));
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        assert_eq!(expand_tabs("\tx", 8), "        x");
+        assert_eq!(expand_tabs("ab\tc\t", 4), "ab  c   ");
+        assert_eq!(expand_tabs("no tabs", 4), "no tabs");
+    }
+
+    #[test]
+    fn test_lcs_length() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let cases = [("abcd", "adb"), ("", "abc"), ("abc", ""), ("abcbdab", "bdcaba"), ("same", "same")];
+        for (a, b) in cases.iter() {
+            let (seq1, seq2) = (to_lines(a), to_lines(b));
+            let expected = lcs(&seq1, &seq2).get(seq1.len(), seq2.len()).unwrap();
+            assert_eq!(lcs_length(&seq1, &seq2), expected, "lcs_length({:?}, {:?})", a, b);
+        }
+    }
+
+    #[test]
+    fn test_similarity() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        assert_eq!(similarity(to_lines("abcd"), &to_lines("abcd")), 100);
+        assert_eq!(similarity(to_lines("abc"), &to_lines("xyz")), 0);
+        assert_eq!(similarity(to_lines("abcd"), &to_lines("adb")), 57);
+        assert_eq!(similarity(to_lines(""), &to_lines("")), 100);
+    }
+
+    #[test]
+    fn test_read_file_missing_newline() {
+        let dir = env::temp_dir();
+        let cases: [(&str, &[u8], &[&str], bool); 4] = [
+            ("rdiff-test-newline.txt", b"a\nb\n", &["a", "b"], false),
+            ("rdiff-test-no-newline.txt", b"a\nb", &["a", "b"], true),
+            ("rdiff-test-crlf.txt", b"a\r\nb\r\n", &["a", "b"], false),
+            ("rdiff-test-empty.txt", b"", &[], false),
+        ];
+        for (name, contents, lines, missing_newline) in cases.iter() {
+            let path = dir.join(name).to_str().unwrap().to_string();
+            std::fs::write(&path, contents).unwrap();
+            let input = read_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(input.lines, *lines, "{}", name);
+            assert_eq!(input.missing_newline, *missing_newline, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_identical() {
+        let same = |a: &str, b: &str| {
+            let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+            identical(&Options::default(), lines(a), lines(b)).unwrap()
+        };
+        assert!(same("a\nb\n", "a\nb\n"));
+        assert!(same("", ""));
+        assert!(!same("a\nb\n", "a\nc\n"));
+        assert!(!same("a\n", "a\nb\n"));
+        assert!(!same("a\nb\n", "a\nb"));
+        let options = Options {expand_tabs: Some(4), ..Default::default()};
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        assert!(identical(&options, lines("\tx\n"), lines("    x\n")).unwrap());
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        let (options, files) = parse_args(&args(&["--label", "old", "a.txt", "--label=new", "b.txt"])).unwrap();
+        assert_eq!(options.labels, args(&["old", "new"]));
+        assert_eq!(files, args(&["a.txt", "b.txt"]));
+        assert!(parse_args(&args(&["a.txt", "--label"])).is_err());
+        assert!(parse_args(&args(&["--label=x", "--label=y", "--label=z", "a", "b"])).is_err());
+
+        let (options, _) = parse_args(&args(&["--width", "30", "-y", "a", "b"])).unwrap();
+        assert_eq!(options.side_by_side, Some(30));
+        let (options, _) = parse_args(&args(&["-y", "a", "b"])).unwrap();
+        assert_eq!(options.side_by_side, Some(SIDE_BY_SIDE_WIDTH));
+        assert!(parse_args(&args(&["--width=0", "a", "b"])).is_err());
+
+        let (options, _) = parse_args(&args(&["-u", "a", "b"])).unwrap();
+        assert_eq!(options.unified, Some(UNIFIED_CONTEXT));
+        let (options, _) = parse_args(&args(&["-c", "0", "-u", "a", "b"])).unwrap();
+        assert_eq!(options.unified, Some(0));
+        let (options, _) = parse_args(&args(&["--context=10", "a", "b"])).unwrap();
+        assert_eq!(options.unified, Some(10));
+        assert!(parse_args(&args(&["-c", "-1", "a", "b"])).is_err());
+        assert!(parse_args(&args(&["--context=x", "a", "b"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--context"])).is_err());
+
+        let (options, files) = parse_args(&args(&["-R", "b.txt", "a.txt"])).unwrap();
+        assert!(options.reverse);
+        assert_eq!(files, args(&["b.txt", "a.txt"]));
+        let ((old, new), names) = file_roles(&options, &files);
+        assert_eq!((old.as_str(), new.as_str()), ("a.txt", "b.txt"));
+        assert_eq!((names.0.as_str(), names.1.as_str()), ("a.txt", "b.txt"));
+
+        let (_, files) = parse_args(&args(&["old.txt", "-"])).unwrap();
+        assert_eq!(files, args(&["old.txt", "-"]));
+        assert!(parse_args(&args(&["-", "-"])).is_err());
+    }
+
+    #[test]
+    fn test_ignore_trailing_space() {
+        let options = Options {ignore_trailing_space: true, ..Default::default()};
+        assert_eq!(comparison_key(&options, "a b \t"), "a b");
+        assert_eq!(comparison_key(&options, "  a"), "  a");
+        assert_eq!(comparison_key(&Options::default(), "a "), "a ");
+
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        assert!(identical(&options, lines("a  \nb\n"), lines("a\nb \n")).unwrap());
+        assert!(!identical(&options, lines("a  b\n"), lines("a b\n")).unwrap());
+    }
+
+    #[test]
+    fn test_ignore_whitespace() {
+        let options = Options {ignore_whitespace: true, ..Default::default()};
+        assert_eq!(comparison_key(&options, "\t  let x =   1; "), "let x = 1;");
+        assert_eq!(comparison_key(&options, " \t "), "");
+
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        assert!(identical(&options, lines("fn f() {\n    x  + 1\n}\n"), lines("fn f() {\n\tx + 1\n}\n")).unwrap());
+        assert!(!identical(&options, lines("x+1\n"), lines("x + 1\n")).unwrap());
+
+        // The reindented line is kept, and printed as it is in the first file
+        let input = |text: &str| prepare_input(&options, read_lines(lines(text)).unwrap());
+        let (file1, file2) = (input("a\n  b\nc\n"), input("a\n\tb\nd\n"));
+        let edits = edit_script(&lcs(file1.keys(), file2.keys()), None, file1.keys(), file2.keys());
+        let mut out = DiffWriter::new(Vec::new());
+        print_edits(&mut out, &options, &edits, &file1, &file2).unwrap();
+        assert_eq!(String::from_utf8(out.out).unwrap(), "\n  a\n    b\n< c\n> d\n");
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        assert_eq!(fold_case("Hello, World"), "hello, world");
+        assert_eq!(fold_case("STRASSE"), fold_case("straße"));
+        assert_eq!(fold_case("ΣΟΦΟΣ"), fold_case("σοφος"));
+        assert_eq!(fold_case("Ǆ"), fold_case("ǆ"));
+
+        let options = Options {ignore_case: true, ..Default::default()};
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        assert!(identical(&options, lines("Grüße\n"), lines("GRÜSSE\n")).unwrap());
+        assert!(!identical(&Options::default(), lines("Grüße\n"), lines("GRÜSSE\n")).unwrap());
+        assert!(identical(&options, lines("Hello\n"), lines("hello\n")).unwrap());
+
+        // With -w as well, both apply to the comparison key
+        let both = Options {ignore_case: true, ignore_whitespace: true, ..Default::default()};
+        assert_eq!(comparison_key(&both, "  Hello   World "), "hello world");
+        assert!(identical(&both, lines("Hello  World\n"), lines("\thello world\n")).unwrap());
+        assert!(!identical(&options, lines("Hello  World\n"), lines("\thello world\n")).unwrap());
+    }
+
+    #[cfg(feature = "normalize-unicode")]
+    #[test]
+    fn test_normalize_unicode() {
+        let options = Options {normalize_unicode: true, ..Default::default()};
+        // "é" precomposed, and as "e" with a combining acute accent
+        assert_eq!(comparison_key(&options, "caf\u{e9}"), comparison_key(&options, "cafe\u{301}"));
+        assert_ne!(comparison_key(&Options::default(), "caf\u{e9}"), comparison_key(&Options::default(), "cafe\u{301}"));
+
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        assert!(identical(&options, lines("caf\u{e9}\n"), lines("cafe\u{301}\n")).unwrap());
+        let both = Options {normalize_unicode: true, ignore_case: true, ..Default::default()};
+        assert!(identical(&both, lines("CAF\u{c9}\n"), lines("cafe\u{301}\n")).unwrap());
+
+        // Only the key is normalized; the line is shown as written
+        let input = prepare_input(&options, read_lines(lines("cafe\u{301}\n")).unwrap());
+        assert_eq!(input.lines[0], "cafe\u{301}");
+    }
+
+    #[test]
+    fn test_key_field() {
+        let options = Options {key_field: Some(2), ..Options::default()};
+        assert_eq!(comparison_key(&options, "10:01 started  worker"), "started");
+        assert_eq!(comparison_key(&options, "10:01"), "");
+        assert_eq!(key_field("  a  b ", 2), Some("b"));
+        assert_eq!(key_field("a", 2), None);
+        let options = Options {key_field: Some(1), ignore_case: true, ..Options::default()};
+        assert_eq!(comparison_key(&options, "Alpha beta"), "alpha");
+
+        let lines = |s: &str| LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+        let options = Options {key_field: Some(2), ..Options::default()};
+        assert!(identical(&options, lines("10:01 start\n10:02 stop\n"), lines("11:30 start\n11:31 stop\n")).unwrap());
+        assert!(!identical(&options, lines("10:01 start\n"), lines("10:01 stop\n")).unwrap());
+        assert!(identical(&options, lines("10:01\n"), lines("10:02\n")).unwrap());
+
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        assert_eq!(parse_args(&args(&["--key-field=3", "a", "b"])).unwrap().0.key_field, Some(3));
+        assert!(parse_args(&args(&["--key-field=0", "a", "b"])).is_err());
+        assert!(parse_args(&args(&["--key-field=x", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn test_edit_script() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let (seq1, seq2) = (to_lines("abcd"), to_lines("adb"));
+        let edits = edit_script(&lcs(&seq1, &seq2), None, &seq1, &seq2);
+        assert_eq!(edits, vec![
+            Edit::Keep(0, 0),
+            Edit::Delete(1),
+            Edit::Delete(2),
+            Edit::Keep(3, 1),
+            Edit::Insert(2),
+        ]);
+    }
+
+    #[test]
+    fn test_minimal() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let (seq1, seq2) = (to_lines("aa"), to_lines("ab"));
+        let lcs_table = lcs(&seq1, &seq2);
+        // Either a can be kept; the default tie-break keeps the second, splitting the changes in two
+        assert_eq!(edit_script(&lcs_table, None, &seq1, &seq2), vec![
+            Edit::Delete(0),
+            Edit::Keep(1, 0),
+            Edit::Insert(1),
+        ]);
+        // --minimal keeps the first, so the changes form one block
+        let blocks = change_blocks(&lcs_table, &seq1, &seq2);
+        assert_eq!(edit_script(&lcs_table, Some(&blocks), &seq1, &seq2), vec![
+            Edit::Keep(0, 0),
+            Edit::Insert(1),
+            Edit::Delete(1),
+        ]);
+
+        // Where the default already has as few blocks as any longest diff, --minimal keeps it
+        let (seq1, seq2) = (to_lines("abcd"), to_lines("adb"));
+        let lcs_table = lcs(&seq1, &seq2);
+        let blocks = change_blocks(&lcs_table, &seq1, &seq2);
+        assert_eq!(edit_script(&lcs_table, Some(&blocks), &seq1, &seq2), edit_script(&lcs_table, None, &seq1, &seq2));
+    }
+
+    #[test]
+    fn test_compute_diff() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let diff = |a: &str, b: &str| {
+            let (seq1, seq2) = (to_lines(a), to_lines(b));
+            let ops: Vec<String> = compute_diff(&edit_script(&lcs(&seq1, &seq2), None, &seq1, &seq2), &seq1, &seq2)
+                .into_iter()
+                .map(|op| match op {
+                    DiffOp::Equal(line) => format!("={}", line),
+                    DiffOp::Insert(line) => format!("+{}", line),
+                    DiffOp::Delete(line) => format!("-{}", line),
+                })
+                .collect();
+            ops.join(" ")
+        };
+        assert_eq!(diff("abcd", "adb"), "=a -b -c =d +b");
+        assert_eq!(diff("adb", "abcd"), "=a -d =b +c +d");
+        assert_eq!(diff("abc", "abc"), "=a =b =c");
+        assert_eq!(diff("", "ab"), "+a +b");
+        assert_eq!(diff("ab", ""), "-a -b");
+        assert_eq!(diff("ab", "cd"), "-a -b +c +d");
+
+        // print_diff prints exactly these steps, in order
+        let (seq1, seq2) = (to_lines("abcd"), to_lines("adb"));
+        let lcs_table = lcs(&seq1, &seq2);
+        let input = |lines: &Vec<String>| Input {lines: lines.clone(), keys: None, missing_newline: false};
+        let mut out = DiffWriter::new(Vec::new());
+        print_diff(&mut out, &Options::default(), &lcs_table, None, &input(&seq1), &input(&seq2)).unwrap();
+        let mut expected = String::from("\n");
+        for op in compute_diff(&edit_script(&lcs_table, None, &seq1, &seq2), &seq1, &seq2) {
+            expected += &match op {
+                DiffOp::Equal(line) => format!("  {}\n", line),
+                DiffOp::Insert(line) => format!("> {}\n", line),
+                DiffOp::Delete(line) => format!("< {}\n", line),
+            };
+        }
+        assert_eq!(String::from_utf8(out.out).unwrap(), expected);
+
+        // It also says whether there were any changes, for the exit status
+        let mut out = DiffWriter::new(Vec::new());
+        assert!(!print_diff(&mut out, &Options::default(), &lcs(&seq1, &seq1), None, &input(&seq1), &input(&seq1)).unwrap());
+    }
+
+    #[test]
+    fn test_print_diff_missing_newline() {
+        let diff = |a: &str, b: &str| {
+            let options = Options::default();
+            let input = |s: &str| {
+                let reader = LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+                prepare_input(&options, read_lines(reader).unwrap())
+            };
+            let (input1, input2) = (input(a), input(b));
+            let lcs_table = lcs(input1.keys(), input2.keys());
+            let mut out = DiffWriter::new(Vec::new());
+            print_diff(&mut out, &options, &lcs_table, None, &input1, &input2).unwrap();
+            String::from_utf8(out.out).unwrap()
+        };
+        // A final line that only differs in its newline is a change, marked on the right side
+        assert_eq!(diff("a\nb", "a\nb\n"), "\n  a\n< b\n\\ No newline at end of file\n> b\n");
+        assert_eq!(diff("a\nb\n", "a\nb"), "\n  a\n< b\n> b\n\\ No newline at end of file\n");
+        assert_eq!(diff("a\nb", "a\nb"), "\n  a\n  b\n\\ No newline at end of file\n");
+        assert_eq!(diff("a\n", "a\n"), "\n  a\n");
+    }
+
+    #[test]
+    fn test_anchored_edits() {
+        let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+        let cases = [("abcd", "adb"), ("", "abc"), ("abc", ""), ("xaybzc", "azbycx"), ("aabba", "abab"), ("same", "same")];
+        for (a, b) in cases.iter() {
+            let (seq1, seq2) = (to_lines(a), to_lines(b));
+            let edits = anchored_edits(&seq1, &seq2);
+            // Every line of both sides appears once, in order, and kept lines really are equal
+            let (mut i, mut j) = (0, 0);
+            for edit in &edits {
+                match *edit {
+                    Edit::Keep(i2, j2) => {
+                        assert_eq!((i2, j2), (i, j), "{:?} vs {:?}", a, b);
+                        assert_eq!(seq1[i], seq2[j]);
+                        i += 1;
+                        j += 1;
+                    }
+                    Edit::Delete(i2) => {
+                        assert_eq!(i2, i);
+                        i += 1;
+                    }
+                    Edit::Insert(j2) => {
+                        assert_eq!(j2, j);
+                        j += 1;
+                    }
+                }
+            }
+            assert_eq!((i, j), (seq1.len(), seq2.len()), "{:?} vs {:?}", a, b);
+        }
+
+        // The longest run of unique lines in the same order is kept: a, b, c and z here
+        assert_eq!(unique_anchors(&to_lines("axbycz"), &to_lines("abcxyz")), vec![(0, 0), (2, 1), (4, 2), (5, 5)]);
+        // Anchors win over a longer common subsequence of repeated lines, which is where the
+        // output can differ from the plain LCS diff
+        let kept = |a: &str, b: &str| -> Vec<usize> {
+            anchored_edits(&to_lines(a), &to_lines(b)).iter().filter_map(|edit| match *edit {
+                Edit::Keep(i, _) => Some(i),
+                _ => None,
+            }).collect()
+        };
+        assert_eq!(kept("xccc", "cccx"), vec![0]);
+        assert_eq!(kept("axbycz", "abcxyz"), vec![0, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_unified_hunks() {
+        let keep = |k: usize| Edit::Keep(k, k);
+        // Changes at 1 and 9, with 7 kept lines between them
+        let mut edits: Vec<Edit> = (0..12).map(keep).collect();
+        edits[1] = Edit::Delete(1);
+        edits[9] = Edit::Insert(9);
+        assert_eq!(unified_hunks(&edits, 3), vec![0..5, 6..12]);
+        assert_eq!(unified_hunks(&edits, 4), vec![0..12]);
+        assert_eq!(unified_hunks(&edits, 0), vec![1..2, 9..10]);
+        assert_eq!(unified_hunks(&(0..5).map(keep).collect::<Vec<Edit>>(), 3), Vec::<Range<usize>>::new());
+    }
+
+    #[test]
+    fn test_print_unified() {
+        let diff = |a: &str, b: &str, context: usize| {
+            let options = Options {unified: Some(context), ..Options::default()};
+            let input = |s: &str| {
+                let reader = LineReader::new(io::Cursor::new(s.as_bytes().to_vec()));
+                prepare_input(&options, read_lines(reader).unwrap())
+            };
+            let (input1, input2) = (input(a), input(b));
+            let edits = edit_script(&lcs(input1.keys(), input2.keys()), None, input1.keys(), input2.keys());
+            let mut out = DiffWriter::new(Vec::new());
+            print_unified(&mut out, &options, ("a.txt", "b.txt"), &edits, &input1, &input2).unwrap();
+            String::from_utf8(out.out).unwrap()
+        };
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            diff(old, new, 3),
+            "--- a.txt\n+++ b.txt\n@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+        assert_eq!(diff(old, new, 0), "--- a.txt\n+++ b.txt\n@@ -3 +3 @@\n-3\n+three\n@@ -12,0 +13 @@\n+13\n");
+        assert_eq!(diff("", "a\nb\n", 3), "--- a.txt\n+++ b.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n");
+        assert_eq!(diff("a\n", "a", 3), "--- a.txt\n+++ b.txt\n@@ -1 +1 @@\n-a\n+a\n\\ No newline at end of file\n");
+        assert_eq!(diff("same\n", "same\n", 3), "");
+    }
+
+    #[test]
+    fn test_reverse() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        // Renders the diff the way main does for these arguments
+        let diff = |argv: &[&str]| {
+            let (options, files) = parse_args(&args(argv)).unwrap();
+            let ((filename1, filename2), (name1, name2)) = file_roles(&options, &files);
+            let (input1, input2) = (read_input(&options, filename1).unwrap(), read_input(&options, filename2).unwrap());
+            let lcs_table = lcs(input1.keys(), input2.keys());
+            let mut out = DiffWriter::new(Vec::new());
+            if options.unified.is_some() {
+                let edits = edit_script(&lcs_table, None, input1.keys(), input2.keys());
+                print_unified(&mut out, &options, (name1, name2), &edits, &input1, &input2).unwrap();
+            } else {
+                print_diff(&mut out, &options, &lcs_table, None, &input1, &input2).unwrap();
+            }
+            out.out
+        };
+        let forward = diff(&["-u", "--label", "old", "--label", "new", "simple-a.txt", "simple-b.txt"]);
+        assert_eq!(diff(&["-R", "-u", "--label", "new", "--label", "old", "simple-b.txt", "simple-a.txt"]), forward);
+        assert!(forward.starts_with(b"--- old\n+++ new\n"));
+        assert_eq!(diff(&["-R", "handout-b.txt", "handout-a.txt"]), diff(&["handout-a.txt", "handout-b.txt"]));
+        assert_ne!(diff(&["handout-b.txt", "handout-a.txt"]), diff(&["handout-a.txt", "handout-b.txt"]));
+    }
+
+    #[test]
+    fn test_print_stat() {
+        let stat = |a: &str, b: &str| {
+            let to_lines = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+            let (seq1, seq2) = (to_lines(a), to_lines(b));
+            let edits = edit_script(&lcs(&seq1, &seq2), None, &seq1, &seq2);
+            let mut out = DiffWriter::new(Vec::new());
+            print_stat(&mut out, "b.txt", &edits).unwrap();
+            String::from_utf8(out.out).unwrap()
+        };
+        assert_eq!(stat("abcd", "adb"), "b.txt: 1 insertions(+), 2 deletions(-)\n");
+        assert_eq!(stat("abc", "abc"), "b.txt: 0 insertions(+), 0 deletions(-)\n");
+        assert_eq!(stat("", "xyz"), "b.txt: 3 insertions(+), 0 deletions(-)\n");
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let side_by_side = |options: &Options, a: &str, b: &str| {
+            let input = |s: &str| Input {
+                lines: s.split_whitespace().map(|line| line.to_string()).collect(),
+                keys: None,
+                missing_newline: false,
+            };
+            let (input1, input2) = (input(a), input(b));
+            let edits = edit_script(&lcs(input1.keys(), input2.keys()), None, input1.keys(), input2.keys());
+            let mut out = DiffWriter::new(Vec::new());
+            print_side_by_side(&mut out, options, 5, &edits, &input1, &input2).unwrap();
+            String::from_utf8(out.out).unwrap()
+        };
+        let options = Options::default();
+        assert_eq!(
+            side_by_side(&options, "same old gone same", "same new same extra"),
+            "same    same\nold   | new\ngone  <\nsame    same\n      > extra\n"
+        );
+        // Long lines are cut to the column width
+        assert_eq!(side_by_side(&options, "abcdefgh", "abcdefgh"), "abcde   abcde\n");
+
+        let options = Options {suppress_common_lines: true, ..Default::default()};
+        assert_eq!(
+            side_by_side(&options, "same old gone same", "same new same extra"),
+            "old   | new\ngone  <\n      > extra\n"
+        );
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("one  two\tthree"), vec!["one", "  ", "two", "\t", "three"]);
+        assert_eq!(split_words(" lead"), vec![" ", "lead"]);
+        assert!(split_words("").is_empty());
+    }
+
+    #[test]
+    fn test_color_words() {
+        assert_eq!(
+            color_words("the quick fox", "the slow fox"),
+            "the \x1b[31mquick\x1b[0m\x1b[32mslow\x1b[0m fox"
+        );
+        assert_eq!(color_words("same", "same"), "same");
+        assert_eq!(color_words("x", "two words"), "\x1b[31mx\x1b[0m\x1b[32mtwo words\x1b[0m");
+        // The merged text keeps the new text's line breaks, and a removed break becomes a space
+        assert_eq!(color_words("a b", "a\nb"), "a\x1b[31m \x1b[0m\nb");
+        assert_eq!(color_words("a\nb", "a b"), "a\x1b[31m \x1b[0m\x1b[32m \x1b[0mb");
+    }
 }
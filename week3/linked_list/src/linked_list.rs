@@ -1,5 +1,6 @@
 use std::fmt;
 use std::option::Option;
+use std::collections::VecDeque;
 
 pub struct LinkedList<T> {
     head: Option<Box<Node<T>>>,
@@ -13,7 +14,7 @@ struct Node<T> {
 
 impl<T> Node<T> {
     pub fn new(value: T, next: Option<Box<Node<T>>>) -> Node<T> {
-        Node::<T> {value: value, next: next}
+        Node::<T> {value, next}
     }
 }
 
@@ -36,27 +37,297 @@ impl<T> LinkedList<T> {
         self.size += 1;
     }
     
+    /// Appends an element to the end of the list. O(n), since the list doesn't keep a tail
+    /// pointer.
+    pub fn push_back(&mut self, value: T) {
+        let mut link = &mut self.head;
+        while let Some(node) = link {
+            link = &mut node.next;
+        }
+        *link = Some(Box::new(Node::<T>::new(value, None)));
+        self.size += 1;
+    }
+
     pub fn pop_front(&mut self) -> Option<T> {
         let node: Box<Node<T>> = self.head.take()?;
         self.head = node.next;
         self.size -= 1;
         Some(node.value)
     }
+
+    /// Returns a reference to the first element, or None if the list is empty. O(1).
+    pub fn first(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    /// Returns a reference to the last element, or None if the list is empty. O(n), since the
+    /// list doesn't keep a tail pointer.
+    ///
+    /// LinkedList is itself an Iterator, so `list.last()` picks the consuming Iterator::last;
+    /// call this one as `LinkedList::last(&list)`.
+    pub fn last(&self) -> Option<&T> {
+        let mut node = self.head.as_ref()?;
+        while let Some(next) = node.next.as_ref() {
+            node = next;
+        }
+        Some(&node.value)
+    }
+
+    /// Returns the 0-based index of the first element matching the predicate, or None.
+    pub fn position<P: Fn(&T) -> bool>(&self, predicate: P) -> Option<usize> {
+        let mut current = &self.head;
+        let mut index = 0;
+        while let Some(node) = current {
+            if predicate(&node.value) {
+                return Some(index);
+            }
+            current = &node.next;
+            index += 1;
+        }
+        None
+    }
+
+    /// Splits the list in two at the given index, like Vec::split_off: self keeps the first index
+    /// elements and the rest are returned as a new list. O(index). Panics if index > size.
+    pub fn split_off(&mut self, index: usize) -> LinkedList<T> {
+        assert!(index <= self.size, "split index (is {}) should be <= size (is {})", index, self.size);
+        let mut link = &mut self.head;
+        for _ in 0..index {
+            link = &mut link.as_mut().unwrap().next;
+        }
+        let tail = LinkedList::<T> {head: link.take(), size: self.size - index};
+        self.size = index;
+        tail
+    }
+
+    /// Exchanges the elements at indices i and j, like slice::swap. Only the values move; the
+    /// nodes stay where they are. O(max(i, j)). Panics if either index is out of bounds.
+    pub fn swap(&mut self, i: usize, j: usize) {
+        let (low, high) = (i.min(j), i.max(j));
+        assert!(high < self.size, "index (is {}) should be < size (is {})", high, self.size);
+        let mut node = self.head.as_mut().unwrap();
+        for _ in 0..low {
+            node = node.next.as_mut().unwrap();
+        }
+        if low == high {
+            return;
+        }
+        // Borrow the low node's value and link separately, so the walk to the high node can go
+        // on while the value is still held
+        let Node {value: low_value, next} = &mut **node;
+        let mut high_node = next.as_mut().unwrap();
+        for _ in low + 1..high {
+            high_node = high_node.next.as_mut().unwrap();
+        }
+        std::mem::swap(low_value, &mut high_node.value);
+    }
+
+    /// Iterates over references to the elements, front to back, without cloning them.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {current: &self.head, remaining: self.size}
+    }
+
+    /// Iterates over (index, &element) pairs, front to back.
+    pub fn iter_enumerate(&self) -> std::iter::Enumerate<Iter<'_, T>> {
+        self.iter().enumerate()
+    }
+
+    /// Iterates over the elements in groups of n, like slice::chunks; the last group is shorter
+    /// if n doesn't divide the size. Panics if n is 0.
+    pub fn chunks(&self, n: usize) -> Chunks<'_, T> {
+        assert!(n != 0, "chunk size must be non-zero");
+        Chunks {iter: self.iter(), size: n}
+    }
+
+    /// Iterates over every run of n consecutive elements, overlapping, like slice::windows.
+    /// Yields nothing if the list is shorter than n. Panics if n is 0.
+    pub fn windows(&self, n: usize) -> Windows<'_, T> {
+        assert!(n != 0, "window size must be non-zero");
+        Windows {iter: self.iter(), window: VecDeque::with_capacity(n), size: n}
+    }
+
+    /// Consumes the list and returns just its first n elements, or all of them if there are
+    /// fewer than n. O(min(n, size)), plus dropping the rest.
+    ///
+    /// These shadow Iterator::take and Iterator::skip, which would return adapters rather than
+    /// lists.
+    pub fn take(mut self, n: usize) -> LinkedList<T> {
+        self.split_off(n.min(self.size));
+        self
+    }
+
+    /// Consumes the list and returns it without its first n elements, or empty if there are
+    /// fewer than n. O(min(n, size)).
+    pub fn skip(mut self, n: usize) -> LinkedList<T> {
+        self.split_off(n.min(self.size))
+    }
+
+    /// Consumes the list and splits it into the elements matching the predicate and the rest,
+    /// each in their original order. The nodes are relinked, not copied.
+    ///
+    /// Like take and skip, this shadows the Iterator method of the same name.
+    pub fn partition<P: Fn(&T) -> bool>(mut self, predicate: P) -> (LinkedList<T>, LinkedList<T>) {
+        let mut matching = LinkedList::<T>::new();
+        let mut rest = LinkedList::<T>::new();
+        let mut matching_tail = &mut matching.head;
+        let mut rest_tail = &mut rest.head;
+        let mut current = self.head.take();
+        while let Some(mut node) = current {
+            current = node.next.take();
+            if predicate(&node.value) {
+                matching.size += 1;
+                matching_tail = &mut matching_tail.insert(node).next;
+            } else {
+                rest.size += 1;
+                rest_tail = &mut rest_tail.insert(node).next;
+            }
+        }
+        (matching, rest)
+    }
+
+    /// Moves the first n elements (mod size) to the end of the list, relinking nodes. O(size).
+    pub fn rotate_left(&mut self, n: usize) {
+        if self.is_empty() || n.is_multiple_of(self.size) {
+            return;
+        }
+        let mut rest = self.split_off(n % self.size);
+        let mut link = &mut rest.head;
+        while let Some(node) = link {
+            link = &mut node.next;
+        }
+        *link = self.head.take();
+        self.head = rest.head.take();
+        self.size += rest.size;
+    }
+
+    /// Moves the last n elements (mod size) to the front of the list. O(size).
+    pub fn rotate_right(&mut self, n: usize) {
+        if !self.is_empty() {
+            self.rotate_left(self.size - n % self.size);
+        }
+    }
+
+    /// Reduces the list front to back into a single value, starting from init, without
+    /// cloning any elements.
+    ///
+    /// As with last, `list.fold(..)` picks the consuming Iterator::fold; call this one as
+    /// `LinkedList::fold(&list, ..)`.
+    pub fn fold<B, F: Fn(B, &T) -> B>(&self, init: B, f: F) -> B {
+        let mut acc = init;
+        let mut current = &self.head;
+        while let Some(node) = current {
+            acc = f(acc, &node.value);
+            current = &node.next;
+        }
+        acc
+    }
 }
 
+impl<T: Ord> LinkedList<T> {
+    /// Returns the smallest element, or None if the list is empty. Of several equal smallest
+    /// elements, the first is returned.
+    ///
+    /// As with last, `list.min()` picks the consuming Iterator::min; call this one (and max) as
+    /// `LinkedList::min(&list)`.
+    pub fn min(&self) -> Option<&T> {
+        self.best_by(|candidate, best| candidate < best)
+    }
+
+    /// Returns the largest element, or None if the list is empty. Of several equal largest
+    /// elements, the last is returned, as with Iterator::max.
+    pub fn max(&self) -> Option<&T> {
+        self.best_by(|candidate, best| candidate >= best)
+    }
+
+    /// Walks the list once, keeping the element for which better(candidate, best so far) holds.
+    fn best_by<F: Fn(&T, &T) -> bool>(&self, better: F) -> Option<&T> {
+        let mut node = self.head.as_ref()?;
+        let mut best = &node.value;
+        while let Some(next) = node.next.as_ref() {
+            if better(&next.value, best) {
+                best = &next.value;
+            }
+            node = next;
+        }
+        Some(best)
+    }
+
+    /// Inserts value into an already sorted list, keeping it sorted: the new node goes before the
+    /// first element greater than it, so after any equal ones. O(size), versus O(size log size)
+    /// for a push followed by a full sort.
+    pub fn insert_sorted(&mut self, value: T) {
+        let mut link = &mut self.head;
+        while link.as_ref().is_some_and(|node| node.value <= value) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+        let node = Box::new(Node::<T>::new(value, link.take()));
+        *link = Some(node);
+        self.size += 1;
+    }
+
+    /// Merges two sorted lists into one sorted list by relinking their nodes; nothing is
+    /// allocated. The merge is stable: equal elements from self come before those from other.
+    pub fn merge(mut self, mut other: LinkedList<T>) -> LinkedList<T> {
+        let size = self.size + other.size;
+        let mut left = self.head.take();
+        let mut right = other.head.take();
+        let mut head: Option<Box<Node<T>>> = None;
+        let mut tail = &mut head;
+        loop {
+            let source = match (&left, &right) {
+                (Some(l), Some(r)) => if l.value <= r.value { &mut left } else { &mut right },
+                // Whatever remains of either list is already sorted, so link it in whole
+                (Some(_), None) => { *tail = left.take(); break; }
+                (None, _) => { *tail = right.take(); break; }
+            };
+            let mut node = source.take().unwrap();
+            *source = node.next.take();
+            tail = &mut tail.insert(node).next;
+        }
+        LinkedList::<T> {head, size}
+    }
+}
+
+impl<T: PartialEq> LinkedList<T> {
+    /// Unlinks every element equal to value and returns how many there were. O(size).
+    pub fn remove_all(&mut self, value: &T) -> usize {
+        let mut removed = 0;
+        let mut link = &mut self.head;
+        while link.is_some() {
+            if link.as_ref().unwrap().value == *value {
+                let node = link.take().unwrap();
+                *link = node.next;
+                removed += 1;
+            } else {
+                link = &mut link.as_mut().unwrap().next;
+            }
+        }
+        self.size -= removed;
+        removed
+    }
+}
+
+impl<T: Copy + Default + std::ops::Add<Output = T>> LinkedList<T> {
+    /// Adds up the elements, giving the default value (zero, for numbers) for an empty list.
+    /// Shadowed by Iterator::sum like min and max, so call it as `LinkedList::sum(&list)`.
+    pub fn sum(&self) -> T {
+        LinkedList::fold(self, T::default(), |acc, &x| acc + x)
+    }
+}
+
+impl<T> Default for LinkedList<T> {
+    fn default() -> Self {
+        LinkedList::new()
+    }
+}
 
 impl<T: std::fmt::Display> fmt::Display for LinkedList<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut current: &Option<Box<Node<T>>> = &self.head;
         let mut result = String::new();
-        loop {
-            match current {
-                Some(node) => {
-                    result = format!("{} {}", result, node.value);
-                    current = &node.next;
-                },
-                None => break,
-            }
+        while let Some(node) = current {
+            result = format!("{} {}", result, node.value);
+            current = &node.next;
         }
         write!(f, "{}", result)
     }
@@ -71,32 +342,45 @@ impl<T> Drop for LinkedList<T> {
     }
 }
 
-impl<T: Clone> Clone for Node<T> {
-    fn clone(&self) -> Self {
-        Node {value: self.value.clone(), next: self.next.clone()}
-    }
-}
-
+// Clone and PartialEq walk the chain in a loop rather than recursing through Node, so that,
+// like Drop, they don't overflow the stack on long lists.
 impl<T: Clone> Clone for LinkedList<T> {
     fn clone(&self) -> Self {
-        LinkedList { head: self.head.clone(), size: self.size.clone() }
-    }
-}
-
-impl<T: PartialEq> PartialEq for Node<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.value == other.value && self.next == other.next
+        let mut head: Option<Box<Node<T>>> = None;
+        let mut tail = &mut head;
+        let mut current = &self.head;
+        while let Some(node) = current {
+            // Same as push_back, but keeping hold of the end so each append is O(1)
+            tail = &mut tail.insert(Box::new(Node::<T>::new(node.value.clone(), None))).next;
+            current = &node.next;
+        }
+        LinkedList::<T> {head, size: self.size}
     }
 }
 
 impl<T: PartialEq> PartialEq for LinkedList<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.size == other.size && self.head == other.head
+        if self.size != other.size {
+            return false;
+        }
+        let mut left = &self.head;
+        let mut right = &other.head;
+        while let (Some(l), Some(r)) = (left, right) {
+            if l.value != r.value {
+                return false;
+            }
+            left = &l.next;
+            right = &r.next;
+        }
+        left.is_none() && right.is_none()
     }
 }
 
+// Both iterators know how many elements are left from the list's size, so they are
+// ExactSizeIterators and size_hint-driven code such as collect can allocate exactly.
 pub struct LinkedListIter<'a, T> {
     current: &'a Option<Box<Node<T>>>,
+    remaining: usize,
 }
 
 
@@ -106,21 +390,93 @@ impl<T: Clone> Iterator for LinkedListIter<'_, T> {
         match self.current {
             Some(node) => {
                 self.current = &node.next;
+                self.remaining -= 1;
                 Some(node.value.clone())
             }
             None => None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl<T: Clone> ExactSizeIterator for LinkedListIter<'_, T> {}
+
 impl<'a, T: Clone> IntoIterator for &'a LinkedList<T> {
     type Item = T;
     type IntoIter = LinkedListIter<'a, T>;
     fn into_iter(self) -> LinkedListIter<'a, T> {
-        LinkedListIter {current: &self.head}
+        LinkedListIter {current: &self.head, remaining: self.size}
     }
 }
 
+pub struct Iter<'a, T> {
+    current: &'a Option<Box<Node<T>>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.current.as_ref()?;
+        self.current = &node.next;
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub struct Windows<'a, T> {
+    iter: Iter<'a, T>,
+    /// The elements of the last window yielded, oldest first.
+    window: VecDeque<&'a T>,
+    size: usize,
+}
+
+impl<'a, T> Iterator for Windows<'a, T> {
+    type Item = Vec<&'a T>;
+    fn next(&mut self) -> Option<Vec<&'a T>> {
+        if self.window.is_empty() {
+            self.window.extend(self.iter.by_ref().take(self.size));
+            if self.window.len() < self.size {
+                self.window.clear();
+                return None;
+            }
+        } else {
+            let value = self.iter.next()?;
+            self.window.pop_front();
+            self.window.push_back(value);
+        }
+        Some(self.window.iter().copied().collect())
+    }
+}
+
+pub struct Chunks<'a, T> {
+    iter: Iter<'a, T>,
+    size: usize,
+}
+
+impl<'a, T> Iterator for Chunks<'a, T> {
+    type Item = Vec<&'a T>;
+    fn next(&mut self) -> Option<Vec<&'a T>> {
+        let chunk: Vec<&T> = self.iter.by_ref().take(self.size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+// The list is its own by-value iterator, so a partly consumed one is dropped by the iterative
+// Drop above like any other list.
 impl<T> Iterator for LinkedList<T> {
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
@@ -129,17 +485,457 @@ impl<T> Iterator for LinkedList<T> {
 }
 
 pub trait ComputeNorm {
+    /// The L^p norm, (sum of |x|^p)^(1/p), for p >= 1. An infinite p gives the largest |x|.
+    fn lp_norm(&self, p: f64) -> f64;
+
+    /// The Euclidean norm, the p = 2 case.
     fn compute_norm(&self) -> f64 {
-        0.0
+        self.lp_norm(2.0)
     }
 }
 
 impl ComputeNorm for LinkedList<f64> {
-    fn compute_norm(&self) -> f64 {
-        let mut sum = 0.0;
-        for x in self {
-            sum += x * x;
+    fn lp_norm(&self, p: f64) -> f64 {
+        // Scaling by the largest |x| keeps |x|^p from overflowing for large p; this way the
+        // result approaches max_abs as p grows, as it should
+        let max_abs = LinkedList::fold(self, 0.0, |max: f64, x| max.max(x.abs()));
+        if max_abs == 0.0 || p.is_infinite() {
+            return max_abs;
+        }
+        let sum = LinkedList::fold(self, 0.0, |sum, x| sum + (x.abs() / max_abs).powf(p));
+        max_abs * sum.powf(1.0 / p)
+    }
+}
+
+#[cfg(test)]
+impl<T> LinkedList<T> {
+    /// Panics unless the size counter agrees with is_empty and with the actual number of nodes.
+    fn check_invariants(&self) {
+        assert_eq!(self.is_empty(), self.get_size() == 0);
+        let mut count = 0;
+        let mut current = &self.head;
+        while let Some(node) = current {
+            count += 1;
+            current = &node.next;
+        }
+        assert_eq!(count, self.get_size(), "node count doesn't match get_size()");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push_front_pop_front_invariants() {
+        let mut list: LinkedList<i32> = LinkedList::new();
+        list.check_invariants();
+        for i in 0..10 {
+            list.push_front(i);
+            list.check_invariants();
+        }
+        while list.pop_front().is_some() {
+            list.check_invariants();
+        }
+        assert!(list.pop_front().is_none());
+        list.check_invariants();
+    }
+
+    #[test]
+    fn test_iterator_next_invariants() {
+        let mut list: LinkedList<i32> = LinkedList::new();
+        for i in 0..5 {
+            list.push_front(i);
+        }
+        assert_eq!(list.next(), Some(4));
+        list.check_invariants();
+        let clone = list.clone();
+        clone.check_invariants();
+        assert_eq!(clone.get_size(), 4);
+    }
+
+    #[test]
+    fn test_default() {
+        let list = LinkedList::<i32>::default();
+        assert!(list.is_empty());
+        list.check_invariants();
+    }
+
+    #[test]
+    fn test_first_last() {
+        let mut list: LinkedList<i32> = LinkedList::new();
+        assert_eq!(list.first(), None);
+        assert_eq!(LinkedList::last(&list), None);
+        list.push_front(1);
+        assert_eq!(list.first(), Some(&1));
+        assert_eq!(LinkedList::last(&list), Some(&1));
+        list.push_front(2);
+        list.push_front(3);
+        assert_eq!(list.first(), Some(&3));
+        assert_eq!(LinkedList::last(&list), Some(&1));
+        list.check_invariants();
+    }
+
+    #[test]
+    fn test_position() {
+        let mut list: LinkedList<i32> = LinkedList::new();
+        assert_eq!(list.position(|&x| x == 1), None);
+        for i in (0..5).rev() {
+            list.push_front(i);
+        }
+        assert_eq!(list.position(|&x| x == 0), Some(0));
+        assert_eq!(list.position(|&x| x > 1), Some(2));
+        assert_eq!(list.position(|&x| x == 4), Some(4));
+        assert_eq!(list.position(|&x| x == 7), None);
+    }
+
+    fn list_of(values: &[i32]) -> LinkedList<i32> {
+        let mut list = LinkedList::new();
+        for &value in values.iter().rev() {
+            list.push_front(value);
+        }
+        list
+    }
+
+    fn to_vec(list: &LinkedList<i32>) -> Vec<i32> {
+        list.into_iter().collect()
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut list = list_of(&[1, 2, 3, 4]);
+        let tail = list.split_off(0);
+        assert!(list.is_empty());
+        assert_eq!(to_vec(&tail), vec![1, 2, 3, 4]);
+        list.check_invariants();
+        tail.check_invariants();
+
+        let mut list = list_of(&[1, 2, 3, 4]);
+        let tail = list.split_off(4);
+        assert_eq!(to_vec(&list), vec![1, 2, 3, 4]);
+        assert!(tail.is_empty());
+        tail.check_invariants();
+
+        let mut list = list_of(&[1, 2, 3, 4]);
+        let tail = list.split_off(1);
+        assert_eq!(to_vec(&list), vec![1]);
+        assert_eq!(to_vec(&tail), vec![2, 3, 4]);
+        list.check_invariants();
+        tail.check_invariants();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_off_out_of_bounds() {
+        list_of(&[1, 2]).split_off(3);
+    }
+
+    #[test]
+    fn test_take_skip() {
+        for &(n, taken, skipped) in [
+            (0, &[][..], &[1, 2, 3][..]),
+            (2, &[1, 2][..], &[3][..]),
+            (3, &[1, 2, 3][..], &[][..]),
+            (5, &[1, 2, 3][..], &[][..]),
+        ].iter() {
+            let take = list_of(&[1, 2, 3]).take(n);
+            assert_eq!(to_vec(&take), taken, "take({})", n);
+            take.check_invariants();
+            let skip = list_of(&[1, 2, 3]).skip(n);
+            assert_eq!(to_vec(&skip), skipped, "skip({})", n);
+            skip.check_invariants();
+        }
+        assert!(list_of(&[]).take(1).is_empty());
+        assert!(list_of(&[]).skip(1).is_empty());
+    }
+
+    #[test]
+    fn test_partition() {
+        let (even, odd) = list_of(&[1, 2, 3, 4, 5, 6, 7]).partition(|&x| x % 2 == 0);
+        assert_eq!(to_vec(&even), vec![2, 4, 6]);
+        assert_eq!(to_vec(&odd), vec![1, 3, 5, 7]);
+        assert_eq!(even.get_size() + odd.get_size(), 7);
+        even.check_invariants();
+        odd.check_invariants();
+
+        let (all, none) = list_of(&[1, 2]).partition(|_| true);
+        assert_eq!(to_vec(&all), vec![1, 2]);
+        assert!(none.is_empty());
+        let (none, empty) = list_of(&[]).partition(|_| true);
+        assert!(none.is_empty() && empty.is_empty());
+    }
+
+    #[test]
+    fn test_min_max_sum() {
+        let list = list_of(&[3, -1, 4, 1, 5, -1]);
+        assert_eq!(LinkedList::min(&list), Some(&-1));
+        assert_eq!(LinkedList::max(&list), Some(&5));
+        assert_eq!(LinkedList::sum(&list), 11);
+        assert_eq!(list.get_size(), 6);
+
+        let empty = list_of(&[]);
+        assert_eq!(LinkedList::min(&empty), None);
+        assert_eq!(LinkedList::max(&empty), None);
+        assert_eq!(LinkedList::sum(&empty), 0);
+
+        let mut floats: LinkedList<f64> = LinkedList::new();
+        floats.push_front(0.5);
+        floats.push_front(1.25);
+        assert_eq!(LinkedList::sum(&floats), 1.75);
+    }
+
+    #[test]
+    fn test_remove_all() {
+        // Matches at the head, in a consecutive run, and at the end
+        let mut list = list_of(&[2, 2, 1, 2, 2, 3, 2]);
+        assert_eq!(list.remove_all(&2), 5);
+        assert_eq!(to_vec(&list), vec![1, 3]);
+        list.check_invariants();
+
+        assert_eq!(list.remove_all(&7), 0);
+        assert_eq!(to_vec(&list), vec![1, 3]);
+
+        let mut empty = list_of(&[]);
+        assert_eq!(empty.remove_all(&1), 0);
+        empty.check_invariants();
+    }
+
+    #[test]
+    fn test_swap() {
+        for &(i, j, expected) in [
+            (0, 1, [2, 1, 3, 4]),
+            (2, 3, [1, 2, 4, 3]),
+            (3, 0, [4, 2, 3, 1]),
+            (1, 3, [1, 4, 3, 2]),
+            (2, 2, [1, 2, 3, 4]),
+        ].iter() {
+            let mut list = list_of(&[1, 2, 3, 4]);
+            list.swap(i, j);
+            assert_eq!(to_vec(&list), expected, "swap({}, {})", i, j);
+            list.check_invariants();
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_swap_out_of_bounds() {
+        list_of(&[1, 2]).swap(0, 2);
+    }
+
+    #[test]
+    fn test_iter_enumerate() {
+        let list = list_of(&[10, 20, 30]);
+        let pairs: Vec<(usize, &i32)> = list.iter_enumerate().collect();
+        assert_eq!(pairs, vec![(0, &10), (1, &20), (2, &30)]);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 3);
+        iter.next();
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        iter.by_ref().count();
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+
+        let mut cloning = (&list).into_iter();
+        cloning.next();
+        assert_eq!(cloning.len(), 2);
+        assert_eq!(list_of(&[]).iter_enumerate().len(), 0);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let list = list_of(&[1, 2, 3, 4, 5, 6]);
+        let chunks: Vec<Vec<&i32>> = list.chunks(2).collect();
+        assert_eq!(chunks, vec![vec![&1, &2], vec![&3, &4], vec![&5, &6]]);
+        let chunks: Vec<Vec<&i32>> = list.chunks(4).collect();
+        assert_eq!(chunks, vec![vec![&1, &2, &3, &4], vec![&5, &6]]);
+        let chunks: Vec<Vec<&i32>> = list.chunks(10).collect();
+        assert_eq!(chunks, vec![vec![&1, &2, &3, &4, &5, &6]]);
+        assert_eq!(list_of(&[]).chunks(3).count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_chunks_zero() {
+        list_of(&[1]).chunks(0);
+    }
+
+    #[test]
+    fn test_windows() {
+        let list = list_of(&[1, 2, 3, 4]);
+        let windows: Vec<Vec<&i32>> = list.windows(1).collect();
+        assert_eq!(windows, vec![vec![&1], vec![&2], vec![&3], vec![&4]]);
+        let windows: Vec<Vec<&i32>> = list.windows(3).collect();
+        assert_eq!(windows, vec![vec![&1, &2, &3], vec![&2, &3, &4]]);
+        let windows: Vec<Vec<&i32>> = list.windows(4).collect();
+        assert_eq!(windows, vec![vec![&1, &2, &3, &4]]);
+        assert_eq!(list.windows(5).count(), 0);
+        assert_eq!(list_of(&[]).windows(1).count(), 0);
+
+        let differences: Vec<i32> = list_of(&[1, 4, 9, 16]).windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert_eq!(differences, vec![3, 5, 7]);
+    }
+
+    #[test]
+    fn test_insert_sorted() {
+        let mut list = list_of(&[]);
+        list.insert_sorted(5);
+        assert_eq!(to_vec(&list), vec![5]);
+        list.insert_sorted(1);
+        assert_eq!(to_vec(&list), vec![1, 5]);
+        list.insert_sorted(3);
+        assert_eq!(to_vec(&list), vec![1, 3, 5]);
+        list.insert_sorted(9);
+        assert_eq!(to_vec(&list), vec![1, 3, 5, 9]);
+        list.insert_sorted(3);
+        assert_eq!(to_vec(&list), vec![1, 3, 3, 5, 9]);
+        list.check_invariants();
+    }
+
+    #[test]
+    fn test_merge() {
+        let merged = list_of(&[1, 3, 5, 7]).merge(list_of(&[2, 3, 6]));
+        assert_eq!(to_vec(&merged), vec![1, 2, 3, 3, 5, 6, 7]);
+        merged.check_invariants();
+
+        let merged = list_of(&[]).merge(list_of(&[1, 2]));
+        assert_eq!(to_vec(&merged), vec![1, 2]);
+        merged.check_invariants();
+        let merged = list_of(&[1, 2]).merge(list_of(&[]));
+        assert_eq!(to_vec(&merged), vec![1, 2]);
+        merged.check_invariants();
+    }
+
+    #[test]
+    fn test_merge_is_stable() {
+        // Order by the first field only, so the second field tells the two equal keys apart
+        #[derive(Debug, PartialEq, Eq)]
+        struct Keyed(i32, &'static str);
+        impl PartialOrd for Keyed {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Keyed {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        let mut left = LinkedList::new();
+        left.push_front(Keyed(1, "left"));
+        let mut right = LinkedList::new();
+        right.push_front(Keyed(1, "right"));
+        let mut merged = left.merge(right);
+        assert_eq!(merged.pop_front(), Some(Keyed(1, "left")));
+        assert_eq!(merged.pop_front(), Some(Keyed(1, "right")));
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(LinkedList::fold(&list_of(&[]), 0, |acc, x| acc + x), 0);
+        assert_eq!(LinkedList::fold(&list_of(&[1, 2, 3, 4]), 0, |acc, x| acc + x), 10);
+
+        let mut words: LinkedList<String> = LinkedList::new();
+        for word in ["c", "b", "a"].iter() {
+            words.push_front(word.to_string());
         }
-        sum.sqrt()
+        assert_eq!(LinkedList::fold(&words, String::new(), |acc, word| acc + word.as_str()), "abc");
+        assert_eq!(words.get_size(), 3);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_push_back() {
+        let mut list = LinkedList::new();
+        list.push_back(1);
+        list.push_back(2);
+        list.push_front(0);
+        list.push_back(3);
+        assert_eq!(to_vec(&list), vec![0, 1, 2, 3]);
+        list.check_invariants();
+    }
+
+    #[test]
+    fn test_clone_long_list() {
+        let mut list = LinkedList::new();
+        for i in 0..200_000 {
+            list.push_front(i);
+        }
+        let clone = list.clone();
+        clone.check_invariants();
+        assert!(list == clone);
+        assert_eq!(clone.first(), Some(&199_999));
+        assert_eq!(LinkedList::last(&clone), Some(&0));
+    }
+
+    #[test]
+    fn test_drop_partly_consumed_into_iter() {
+        let mut list = LinkedList::new();
+        for i in 0..200_000 {
+            list.push_front(i);
+        }
+        // into_iter() on a list is the list itself
+        assert_eq!(list.next(), Some(199_999));
+        drop(list);
+    }
+
+    #[test]
+    fn test_lp_norm() {
+        let mut list: LinkedList<f64> = LinkedList::new();
+        for &x in [3.0, -4.0, 1.0].iter() {
+            list.push_front(x);
+        }
+        assert!((list.lp_norm(1.0) - 8.0).abs() < 1e-12);
+        assert!((list.lp_norm(2.0) - 26f64.sqrt()).abs() < 1e-12);
+        assert_eq!(list.compute_norm(), list.lp_norm(2.0));
+        // Large p tends to the largest absolute value, without overflowing on the way
+        assert!((list.lp_norm(1000.0) - 4.0).abs() < 1e-3);
+        assert_eq!(list.lp_norm(f64::INFINITY), 4.0);
+        assert_eq!(LinkedList::<f64>::new().lp_norm(1.0), 0.0);
+    }
+
+    #[test]
+    fn test_eq_different_sizes() {
+        assert!(list_of(&[1, 2, 3]) != list_of(&[1, 2]));
+        assert!(list_of(&[1, 2]) != list_of(&[1, 2, 3]));
+        assert!(list_of(&[]) != list_of(&[1]));
+        assert!(list_of(&[1, 2]) != list_of(&[1, 3]));
+        assert!(list_of(&[]) == list_of(&[]));
+        assert!(list_of(&[1, 2]) == list_of(&[1, 2]));
+    }
+
+    #[test]
+    fn test_rotate_left() {
+        for &(n, expected) in [
+            (0, [1, 2, 3, 4]),
+            (1, [2, 3, 4, 1]),
+            (3, [4, 1, 2, 3]),
+            (4, [1, 2, 3, 4]),
+            (6, [3, 4, 1, 2]),
+        ].iter() {
+            let mut list = list_of(&[1, 2, 3, 4]);
+            list.rotate_left(n);
+            assert_eq!(to_vec(&list), expected, "rotate_left({})", n);
+            list.check_invariants();
+        }
+        let mut empty = list_of(&[]);
+        empty.rotate_left(3);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_rotate_right() {
+        for &(n, expected) in [
+            (0, [1, 2, 3, 4]),
+            (1, [4, 1, 2, 3]),
+            (4, [1, 2, 3, 4]),
+            (9, [4, 1, 2, 3]),
+        ].iter() {
+            let mut list = list_of(&[1, 2, 3, 4]);
+            list.rotate_right(n);
+            assert_eq!(to_vec(&list), expected, "rotate_right({})", n);
+            list.check_invariants();
+        }
+        let mut empty = list_of(&[]);
+        empty.rotate_right(1);
+        assert!(empty.is_empty());
+    }
+}
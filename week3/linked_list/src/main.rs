@@ -14,7 +14,8 @@ fn main() {
     println!("top element: {}", list.pop_front().unwrap());
     println!("{}", list);
     println!("size: {}", list.get_size());
-    println!("{}", list.to_string()); // ToString impl for anything impl Display
+    let list_string = list.to_string(); // ToString impl for anything impl Display
+    println!("{}", list_string);
 
     let list_clone = list.clone();
     println!("{}", list == list_clone);
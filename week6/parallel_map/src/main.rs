@@ -1,15 +1,499 @@
-use crossbeam_channel;
+use crossbeam_deque::{Steal, Stealer, Worker};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{thread, time};
 
-fn parallel_map<T, U, F>(mut input_vec: Vec<T>, num_threads: usize, f: F) -> Vec<U>
+pub fn parallel_map<T, U, F>(input_vec: Vec<T>, num_threads: usize, f: F) -> Vec<U>
 where
     F: FnOnce(T) -> U + Send + Copy + 'static,
     T: Send + 'static,
-    U: Send + 'static + Default,
+    U: Send + 'static,
 {
-    let mut output_vec: Vec<U> = Vec::with_capacity(input_vec.len());
-    output_vec.resize_with(input_vec.len(), Default::default);
+    ParMap::new().threads(num_threads).map(input_vec, f)
+}
+
+/// Builder for configuring a parallel map, e.g.
+/// `ParMap::new().threads(8).chunk_size(64).bounded(1000).map(input, f)`.
+/// Every setting has a default, so `ParMap::new().map(input, f)` works as is.
+pub struct ParMap {
+    num_threads: usize,
+    chunk_size: usize,
+    capacity: Option<usize>,
+    stack_size: Option<usize>,
+    thread_name: Option<String>,
+}
+
+impl Default for ParMap {
+    fn default() -> Self {
+        ParMap::new()
+    }
+}
+
+impl ParMap {
+    /// Creates a builder with every setting at its default.
+    pub fn new() -> ParMap {
+        ParMap {
+            num_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            chunk_size: 1,
+            capacity: None,
+            stack_size: None,
+            thread_name: None,
+        }
+    }
+
+    /// Sets the number of worker threads. Defaults to the number of CPUs available. Panics if
+    /// num_threads is 0.
+    pub fn threads(mut self, num_threads: usize) -> ParMap {
+        assert!(num_threads > 0, "parallel map needs at least one thread");
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Sets how many consecutive inputs a worker takes from the queue at once. Larger chunks cut
+    /// channel overhead for cheap functions. Defaults to 1. Panics if chunk_size is 0.
+    pub fn chunk_size(mut self, chunk_size: usize) -> ParMap {
+        assert!(chunk_size > 0, "chunk size must be at least 1");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Limits the input queue to this many chunks, so inputs are handed over as workers free up
+    /// instead of all at once. Defaults to unbounded. With map_iter, this also bounds how many
+    /// inputs exist at any one time.
+    pub fn bounded(mut self, capacity: usize) -> ParMap {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the stack size in bytes of each worker thread. Defaults to the standard library's
+    /// default for spawned threads.
+    pub fn stack_size(mut self, stack_size: usize) -> ParMap {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Names the worker threads "<name>-0", "<name>-1", and so on. Defaults to unnamed threads.
+    pub fn thread_name(mut self, name: &str) -> ParMap {
+        self.thread_name = Some(name.to_string());
+        self
+    }
+
+    /// Applies f to every element of input_vec and returns the results in input order.
+    pub fn map<T, U, F>(&self, input_vec: Vec<T>, f: F) -> Vec<U>
+    where
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        let num_inputs = input_vec.len();
+        collect_in_order(self.map_unordered(input_vec, f), num_inputs)
+    }
+
+    /// Like map, but takes the inputs from an iterator, which a producer thread runs to feed the
+    /// workers. With a bounded queue the producer waits for room, so only about
+    /// (capacity + num_threads + 1) * chunk_size inputs are alive at once, however many there are
+    /// in all, whereas map needs every one of them in input_vec before it starts. The results are
+    /// still collected in full, in input order.
+    pub fn map_iter<I, T, U, F>(&self, inputs: I, f: F) -> Vec<U>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        let (input_sender, input_receiver) = match self.capacity {
+            Some(capacity) => crossbeam_channel::bounded::<(usize, Vec<T>)>(capacity),
+            None => crossbeam_channel::unbounded::<(usize, Vec<T>)>(),
+        };
+        let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, Vec<U>)>();
+        let handles = self.spawn_workers(input_receiver, output_sender, f);
+        let chunk_size = self.chunk_size;
+        let inputs = inputs.into_iter();
+        let producer = thread::spawn(move || send_chunks(inputs, chunk_size, &input_sender));
+
+        let results: Vec<(usize, Vec<U>)> = output_receiver.iter().collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let num_inputs = producer.join().unwrap();
+        let results = results.into_iter().flat_map(|(start, outputs)| {
+            outputs.into_iter().enumerate().map(move |(offset, output)| (start + offset, output))
+        });
+        collect_in_order(results, num_inputs)
+    }
+
+    /// Applies f to every element of input_vec, returning the results tagged with the index of
+    /// their input in whatever order the workers produced them.
+    fn map_unordered<T, U, F>(&self, input_vec: Vec<T>, f: F) -> Vec<(usize, U)>
+    where
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        let (input_sender, input_receiver) = match self.capacity {
+            Some(capacity) => crossbeam_channel::bounded::<(usize, Vec<T>)>(capacity),
+            None => crossbeam_channel::unbounded::<(usize, Vec<T>)>(),
+        };
+        let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, Vec<U>)>();
+
+        // Workers have to be running before the inputs are sent, in case the queue is bounded
+        let handles = self.spawn_workers(input_receiver, output_sender, f);
+        send_chunks(input_vec, self.chunk_size, &input_sender);
+        drop(input_sender);
+
+        let results: Vec<(usize, Vec<U>)> = output_receiver.iter().collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        results
+            .into_iter()
+            .flat_map(|(start, outputs)| {
+                outputs.into_iter().enumerate().map(move |(offset, output)| (start + offset, output))
+            })
+            .collect()
+    }
+
+    /// Like map, but returns an iterator over the results in input order, each yielded as soon as
+    /// it and every result before it are ready, rather than after the whole map. Results that
+    /// finish ahead of an earlier one wait in a reorder buffer (see InOrder). That buffer usually
+    /// stays small, but in the worst case, when results arrive in reverse order, it fills up with
+    /// every result but the first before anything can be yielded.
+    pub fn stream<T, U, F>(&self, input_vec: Vec<T>, f: F) -> InOrder<Stream<U>, U>
+    where
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        InOrder::new(self.stream_unordered(input_vec, f))
+    }
+
+    /// Like stream, but yields (input index, result) pairs in whatever order the workers produce
+    /// them, so nothing is ever buffered. Dropping the iterator early makes the workers stop after
+    /// their current chunk.
+    pub fn stream_unordered<T, U, F>(&self, input_vec: Vec<T>, f: F) -> Stream<U>
+    where
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        let (input_sender, input_receiver) = match self.capacity {
+            Some(capacity) => crossbeam_channel::bounded::<(usize, Vec<T>)>(capacity),
+            None => crossbeam_channel::unbounded::<(usize, Vec<T>)>(),
+        };
+        let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, Vec<U>)>();
+        let mut handles = self.spawn_workers(input_receiver, output_sender, f);
+        // The caller has to get the iterator back before every input is queued, so a bounded
+        // queue is fed from its own thread
+        let chunk_size = self.chunk_size;
+        handles.push(thread::spawn(move || {
+            send_chunks(input_vec, chunk_size, &input_sender);
+        }));
+        Stream {
+            receiver: output_receiver,
+            chunk: Vec::new().into_iter().enumerate(),
+            start: 0,
+            handles,
+        }
+    }
+
+    /// Starts the worker threads, which apply f to each chunk of inputs from input_receiver and
+    /// send the results, tagged with the index of the chunk's first input, to output_sender.
+    fn spawn_workers<T, U, F>(
+        &self,
+        input_receiver: crossbeam_channel::Receiver<(usize, Vec<T>)>,
+        output_sender: crossbeam_channel::Sender<(usize, Vec<U>)>,
+        f: F,
+    ) -> Vec<thread::JoinHandle<()>>
+    where
+        F: FnOnce(T) -> U + Send + Copy + 'static,
+        T: Send + 'static,
+        U: Send + 'static,
+    {
+        let mut handles = vec![];
+        for i in 0..self.num_threads {
+            let mut builder = thread::Builder::new();
+            if let Some(stack_size) = self.stack_size {
+                builder = builder.stack_size(stack_size);
+            }
+            if let Some(name) = &self.thread_name {
+                builder = builder.name(format!("{}-{}", name, i));
+            }
+            let c_output_sender = output_sender.clone();
+            let c_input_receiver = input_receiver.clone();
+            let handle = builder
+                .spawn(move || {
+                    // recv parks the thread until an input arrives or the channel closes, so
+                    // idle workers sleep through gaps in the work rather than spinning
+                    while let Ok((start, inputs)) = c_input_receiver.recv() {
+                        let mut res = Vec::with_capacity(inputs.len());
+                        for input in inputs {
+                            res.push(f(input));
+                        }
+                        // Only fails once a stream has been dropped, when nobody wants the rest
+                        if c_output_sender.send((start, res)).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("failed to spawn worker thread");
+            handles.push(handle);
+        }
+        handles
+    }
+}
+
+/// Sends the inputs to ParMap workers in chunks of chunk_size, each tagged with the index of its
+/// first input, and returns how many were sent. Stops early if every worker has gone away.
+fn send_chunks<T>(
+    inputs: impl IntoIterator<Item = T>,
+    chunk_size: usize,
+    input_sender: &crossbeam_channel::Sender<(usize, Vec<T>)>,
+) -> usize {
+    let mut inputs = inputs.into_iter();
+    let mut start = 0;
+    loop {
+        let chunk: Vec<T> = inputs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk_len = chunk.len();
+        if input_sender.send((start, chunk)).is_err() {
+            break;
+        }
+        start += chunk_len;
+    }
+    start
+}
+
+/// Iterator returned by ParMap::stream_unordered, yielding (input index, result) pairs as the
+/// workers produce them.
+pub struct Stream<U> {
+    receiver: crossbeam_channel::Receiver<(usize, Vec<U>)>,
+    /// The rest of the chunk of results being yielded, whose first input had index start.
+    chunk: std::iter::Enumerate<std::vec::IntoIter<U>>,
+    start: usize,
+    handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl<U> Iterator for Stream<U> {
+    type Item = (usize, U);
+
+    fn next(&mut self) -> Option<(usize, U)> {
+        loop {
+            if let Some((offset, output)) = self.chunk.next() {
+                return Some((self.start + offset, output));
+            }
+            match self.receiver.recv() {
+                Ok((start, outputs)) => {
+                    self.start = start;
+                    self.chunk = outputs.into_iter().enumerate();
+                }
+                Err(_) => {
+                    // Every worker is done; pass on a panic in f rather than ending quietly
+                    // with results missing
+                    for handle in self.handles.drain(..) {
+                        if let Err(panic) = handle.join() {
+                            std::panic::resume_unwind(panic);
+                        }
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Reorder buffer that turns (index, output) pairs arriving in any order into the outputs in
+/// index order, starting from 0. Each output is yielded as soon as every one before it has been,
+/// while outputs that arrive early are held until then. Like collect_in_order, panics if an index
+/// is delivered twice or is still missing when the pairs run out.
+pub struct InOrder<I, U> {
+    results: I,
+    buffer: HashMap<usize, U>,
+    next_index: usize,
+}
+
+impl<I, U> InOrder<I, U>
+where
+    I: Iterator<Item = (usize, U)>,
+{
+    pub fn new(results: I) -> InOrder<I, U> {
+        InOrder {
+            results,
+            buffer: HashMap::new(),
+            next_index: 0,
+        }
+    }
+}
+
+impl<I, U> Iterator for InOrder<I, U>
+where
+    I: Iterator<Item = (usize, U)>,
+{
+    type Item = U;
+
+    fn next(&mut self) -> Option<U> {
+        if let Some(output) = self.buffer.remove(&self.next_index) {
+            self.next_index += 1;
+            return Some(output);
+        }
+        loop {
+            match self.results.next() {
+                Some((index, output)) if index == self.next_index => {
+                    self.next_index += 1;
+                    return Some(output);
+                }
+                Some((index, output)) => {
+                    assert!(
+                        index > self.next_index && !self.buffer.contains_key(&index),
+                        "result for index {} delivered twice",
+                        index
+                    );
+                    self.buffer.insert(index, output);
+                }
+                None => {
+                    assert!(self.buffer.is_empty(), "no result for index {}", self.next_index);
+                    return None;
+                }
+            }
+        }
+    }
+}
 
+/// Like parallel_map, but the input is split up front into num_threads contiguous chunks, one
+/// per worker. A worker that finishes its own chunk steals pending elements from the others, so
+/// when some elements are much more expensive than the rest, fast workers take over the
+/// remaining work of slow ones instead of sitting idle.
+pub fn parallel_map_balanced<T, U, F>(input_vec: Vec<T>, num_threads: usize, f: F) -> Vec<U>
+where
+    F: FnOnce(T) -> U + Send + Copy + 'static,
+    T: Send + 'static,
+    U: Send + 'static,
+{
+    assert!(num_threads > 0, "parallel map needs at least one thread");
+    let num_inputs = input_vec.len();
+    let chunk_len = num_inputs.div_ceil(num_threads).max(1);
+    let queues: Vec<Worker<(usize, T)>> = (0..num_threads).map(|_| Worker::new_fifo()).collect();
+    for (index, input) in input_vec.into_iter().enumerate() {
+        queues[index / chunk_len].push((index, input));
+    }
+    let stealers: Arc<Vec<Stealer<(usize, T)>>> = Arc::new(queues.iter().map(Worker::stealer).collect());
+
+    let handles: Vec<_> = queues
+        .into_iter()
+        .map(|queue| {
+            let c_stealers = stealers.clone();
+            thread::spawn(move || {
+                let mut outputs = Vec::new();
+                while let Some((index, input)) = find_task(&queue, &c_stealers) {
+                    outputs.push((index, f(input)));
+                }
+                outputs
+            })
+        })
+        .collect();
+    let results: Vec<(usize, U)> = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+    collect_in_order(results, num_inputs)
+}
+
+/// Takes the next task from the worker's own queue, or steals a batch from another worker. All
+/// tasks are queued before the workers start, so once every queue is empty there's nothing left.
+fn find_task<T>(queue: &Worker<T>, stealers: &[Stealer<T>]) -> Option<T> {
+    queue.pop().or_else(|| {
+        // A steal can fail with Retry when it races with another thread; try again until every
+        // queue reports Empty or something is stolen
+        std::iter::repeat_with(|| {
+            stealers
+                .iter()
+                .map(|stealer| stealer.steal_batch_and_pop(queue))
+                .collect::<Steal<T>>()
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    })
+}
+
+/// Runs stage2(stage1(x)) for every element of input_vec as a two-stage pipeline, with its own
+/// pool of threads for each stage, and returns the results in input order.
+///
+/// Stage-1 results go straight to the stage-2 workers over a bounded channel holding at most
+/// 2 * stage2_threads values. Running parallel_map twice would materialize a whole intermediate
+/// Vec<U>; here at most that many values plus one per thread are alive at any time, and stage 2
+/// starts working as soon as stage 1 produces its first value. If stage 2 is slower, stage-1
+/// workers block on the channel instead of piling up intermediate values.
+pub fn parallel_map_pipeline<T, U, V, F, G>(
+    mut input_vec: Vec<T>,
+    stage1_threads: usize,
+    stage1: F,
+    stage2_threads: usize,
+    stage2: G,
+) -> Vec<V>
+where
+    F: FnOnce(T) -> U + Send + Copy + 'static,
+    G: FnOnce(U) -> V + Send + Copy + 'static,
+    T: Send + 'static,
+    U: Send + 'static,
+    V: Send + 'static,
+{
+    assert!(stage1_threads > 0 && stage2_threads > 0, "each stage needs at least one thread");
+    let num_inputs = input_vec.len();
+    let (input_sender, input_receiver) = crossbeam_channel::unbounded::<(usize, T)>();
+    let (middle_sender, middle_receiver) = crossbeam_channel::bounded::<(usize, U)>(2 * stage2_threads);
+    let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, V)>();
+    let mut handles = vec![];
+
+    while let Some(input) = input_vec.pop() {
+        input_sender.send((input_vec.len(), input)).expect("wrong input sending");
+    }
+    drop(input_sender);
+
+    for _ in 0..stage1_threads {
+        let c_input_receiver = input_receiver.clone();
+        let c_middle_sender = middle_sender.clone();
+        handles.push(thread::spawn(move || {
+            while let Ok((counter, input)) = c_input_receiver.recv() {
+                c_middle_sender.send((counter, stage1(input))).expect("wrong middle sending");
+            }
+        }));
+    }
+    // Stage 2 sees the channel close once the last stage-1 worker drops its sender
+    drop(middle_sender);
+
+    for _ in 0..stage2_threads {
+        let c_middle_receiver = middle_receiver.clone();
+        let c_output_sender = output_sender.clone();
+        handles.push(thread::spawn(move || {
+            while let Ok((counter, middle)) = c_middle_receiver.recv() {
+                c_output_sender.send((counter, stage2(middle))).expect("wrong output sending");
+            }
+        }));
+    }
+    drop(middle_receiver);
+    drop(output_sender);
+
+    let results: Vec<(usize, V)> = output_receiver.iter().collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    collect_in_order(results, num_inputs)
+}
+
+/// Like parallel_map, but also reports how many elements each worker processed and how long the
+/// whole map took, which shows whether the work was spread evenly across the threads. Returns
+/// (outputs in input order, per-worker counts, wall-clock time).
+pub fn parallel_map_timed<T, U, F>(mut input_vec: Vec<T>, num_threads: usize, f: F) -> (Vec<U>, Vec<usize>, Duration)
+where
+    F: FnOnce(T) -> U + Send + Copy + 'static,
+    T: Send + 'static,
+    U: Send + 'static,
+{
+    assert!(num_threads > 0, "parallel map needs at least one thread");
+    let started = Instant::now();
+    let num_inputs = input_vec.len();
     let (input_sender, input_receiver) = crossbeam_channel::unbounded::<(usize, T)>();
     let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, U)>();
     let mut handles = vec![];
@@ -22,24 +506,248 @@ where
     for _ in 0..num_threads {
         let c_output_sender = output_sender.clone();
         let c_input_receiver = input_receiver.clone();
+        handles.push(thread::spawn(move || {
+            // Counted locally and reported once the input channel is drained
+            let mut processed = 0;
+            while let Ok((counter, input)) = c_input_receiver.recv() {
+                c_output_sender.send((counter, f(input))).expect("wrong output sending");
+                processed += 1;
+            }
+            processed
+        }));
+    }
+    drop(output_sender);
+
+    let results: Vec<(usize, U)> = output_receiver.iter().collect();
+    let counts: Vec<usize> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    (collect_in_order(results, num_inputs), counts, started.elapsed())
+}
+
+/// Applies f to every element of slice in place, splitting the slice into num_threads contiguous
+/// chunks that are each mutated by one scoped thread. Nothing is allocated for the results.
+pub fn parallel_map_mut<T, F>(slice: &mut [T], num_threads: usize, f: F)
+where
+    F: Fn(&mut T) + Sync,
+    T: Send,
+{
+    assert!(num_threads > 0, "parallel map needs at least one thread");
+    if slice.is_empty() {
+        return;
+    }
+    // Round up so that there are at most num_threads chunks
+    let chunk_len = slice.len().div_ceil(num_threads);
+    let f = &f;
+    thread::scope(|scope| {
+        for chunk in slice.chunks_mut(chunk_len) {
+            scope.spawn(move || chunk.iter_mut().for_each(f));
+        }
+    });
+}
+
+/// Puts (index, output) pairs back in input order. Panics if any index in 0..len is missing or
+/// delivered twice, since either would mean a result got lost or misplaced.
+fn collect_in_order<U, I>(results: I, len: usize) -> Vec<U>
+where
+    I: IntoIterator<Item = (usize, U)>,
+{
+    let mut output_vec: Vec<Option<U>> = Vec::with_capacity(len);
+    output_vec.resize_with(len, || None);
+    for (index, output) in results {
+        assert!(index < len, "result index {} out of range for {} inputs", index, len);
+        assert!(output_vec[index].is_none(), "result for index {} delivered twice", index);
+        output_vec[index] = Some(output);
+    }
+    output_vec
+        .into_iter()
+        .enumerate()
+        .map(|(index, output)| output.unwrap_or_else(|| panic!("no result for index {}", index)))
+        .collect()
+}
+
+/// Like parallel_map, but f produces key-value pairs that are collected into a HashMap. If several
+/// inputs produce the same key, the value from the input that came last in input_vec wins, just
+/// as if the pairs had been inserted sequentially.
+pub fn parallel_map_to_map<T, K, V, F>(input_vec: Vec<T>, num_threads: usize, f: F) -> HashMap<K, V>
+where
+    F: FnOnce(T) -> (K, V) + Send + Copy + 'static,
+    T: Send + 'static,
+    K: Eq + Hash + Send + 'static,
+    V: Send + 'static,
+{
+    // Pairs arrive in whatever order the workers finish, so keep the index of the input that
+    // produced each value and only let later inputs overwrite it
+    let mut indexed: HashMap<K, (usize, V)> = HashMap::new();
+    for (index, (key, value)) in ParMap::new().threads(num_threads).map_unordered(input_vec, f) {
+        match indexed.get(&key) {
+            Some((existing, _)) if *existing > index => {}
+            _ => {
+                indexed.insert(key, (index, value));
+            }
+        }
+    }
+    indexed.into_iter().map(|(key, (_, value))| (key, value)).collect()
+}
+
+/// Returned by parallel_map_with_timeout when a task runs longer than the timeout.
+#[derive(Debug, PartialEq)]
+pub struct TaskTimedOut {
+    /// Index in the input vector of the element whose task got stuck.
+    pub index: usize,
+}
+
+/// Progress reports sent from the workers of parallel_map_with_timeout.
+enum TaskEvent<U> {
+    Started(usize, Instant),
+    Finished(usize, U),
+}
+
+/// Like parallel_map, but gives up if any single call to f takes longer than timeout, returning
+/// the index of the stuck element. Threads can't be killed safely, so a stuck worker keeps running
+/// (and its thread leaks) until f returns; the other workers stop taking new elements.
+pub fn parallel_map_with_timeout<T, U, F>(
+    mut input_vec: Vec<T>,
+    num_threads: usize,
+    timeout: Duration,
+    f: F,
+) -> Result<Vec<U>, TaskTimedOut>
+where
+    F: FnOnce(T) -> U + Send + Copy + 'static,
+    T: Send + 'static,
+    U: Send + 'static,
+{
+    assert!(num_threads > 0, "parallel map needs at least one thread");
+    let num_inputs = input_vec.len();
+    let (input_sender, input_receiver) = crossbeam_channel::unbounded::<(usize, T)>();
+    let (event_sender, event_receiver) = crossbeam_channel::unbounded::<TaskEvent<U>>();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let mut handles = vec![];
+
+    while let Some(input) = input_vec.pop() {
+        input_sender.send((input_vec.len(), input)).expect("wrong input sending");
+    }
+    drop(input_sender);
+
+    for _ in 0..num_threads {
+        let c_event_sender = event_sender.clone();
+        let c_input_receiver = input_receiver.clone();
+        let c_cancelled = cancelled.clone();
         let handle = thread::spawn(move || {
             while let Ok((counter, input)) = c_input_receiver.recv() {
+                if c_cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                // The coordinator may already have given up, so sends are allowed to fail
+                let _ = c_event_sender.send(TaskEvent::Started(counter, Instant::now()));
+                let res = f(input);
+                let _ = c_event_sender.send(TaskEvent::Finished(counter, res));
+            }
+        });
+        handles.push(handle);
+    }
+    drop(event_sender);
+
+    let mut results = Vec::with_capacity(num_inputs);
+    let mut running: HashMap<usize, Instant> = HashMap::new();
+    loop {
+        // Wait until the oldest running task would time out
+        let event = match running.iter().min_by_key(|(_, started)| **started) {
+            Some((&index, &started)) => {
+                let waited = started.elapsed();
+                if waited >= timeout {
+                    cancelled.store(true, Ordering::SeqCst);
+                    return Err(TaskTimedOut { index });
+                }
+                match event_receiver.recv_timeout(timeout - waited) {
+                    Ok(event) => event,
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout) => continue,
+                    Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match event_receiver.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+        match event {
+            TaskEvent::Started(index, started) => {
+                running.insert(index, started);
+            }
+            TaskEvent::Finished(index, output) => {
+                running.remove(&index);
+                results.push((index, output));
+            }
+        }
+    }
+
+    // Every worker has exited, so joining can't block; this re-raises any panic from f
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    Ok(collect_in_order(results, num_inputs))
+}
+
+/// Like parallel_map, but f can fail. Once a call returns Err, the workers stop taking new
+/// elements, and that error is returned after the calls already under way have finished. If more
+/// than one element fails, the error returned is whichever was reported first, which depends on
+/// timing; it is not necessarily the error for the lowest-index element.
+pub fn try_parallel_map<T, U, E, F>(input_vec: Vec<T>, num_threads: usize, f: F) -> Result<Vec<U>, E>
+where
+    F: FnOnce(T) -> Result<U, E> + Send + Copy + 'static,
+    T: Send + 'static,
+    U: Send + 'static,
+    E: Send + 'static,
+{
+    assert!(num_threads > 0, "parallel map needs at least one thread");
+    let num_inputs = input_vec.len();
+    let (input_sender, input_receiver) = crossbeam_channel::unbounded::<(usize, T)>();
+    let (output_sender, output_receiver) = crossbeam_channel::unbounded::<(usize, Result<U, E>)>();
+    let failed = Arc::new(AtomicBool::new(false));
+    let mut handles = vec![];
+
+    for (index, input) in input_vec.into_iter().enumerate() {
+        input_sender.send((index, input)).expect("wrong input sending");
+    }
+    drop(input_sender);
+
+    for _ in 0..num_threads {
+        let c_output_sender = output_sender.clone();
+        let c_input_receiver = input_receiver.clone();
+        let c_failed = failed.clone();
+        let handle = thread::spawn(move || {
+            while let Ok((index, input)) = c_input_receiver.recv() {
+                if c_failed.load(Ordering::SeqCst) {
+                    break;
+                }
                 let res = f(input);
-                c_output_sender.send((counter, res)).expect("wrong output sending");
+                if res.is_err() {
+                    // Stops the other workers too, without waiting for the error to be received
+                    c_failed.store(true, Ordering::SeqCst);
+                }
+                c_output_sender.send((index, res)).expect("wrong output sending");
             }
-            drop(c_output_sender);
         });
         handles.push(handle);
     }
     drop(output_sender);
 
+    let mut results = Vec::with_capacity(num_inputs);
+    let mut error = None;
+    for (index, res) in output_receiver.iter() {
+        match res {
+            Ok(output) => results.push((index, output)),
+            Err(err) => {
+                error.get_or_insert(err);
+            }
+        }
+    }
+    // The receiver has drained, so every worker has exited; this re-raises any panic from f
     for handle in handles {
         handle.join().unwrap();
     }
-    while let Ok((index, output)) = output_receiver.recv() {
-        output_vec[index] = output;
+    match error {
+        Some(err) => Err(err),
+        None => Ok(collect_in_order(results, num_inputs)),
     }
-    output_vec
 }
 
 fn main() {
@@ -51,3 +759,335 @@ fn main() {
     });
     println!("squares: {:?}", squares);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map_order() {
+        let input: Vec<usize> = (0..200).collect();
+        let output = parallel_map(input.clone(), 8, |n| {
+            // Finish later elements first to scramble the arrival order
+            thread::sleep(Duration::from_micros(((200 - n) * 10) as u64));
+            n * 2
+        });
+        assert_eq!(output, input.iter().map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parallel_map_without_default() {
+        // No Default impl, so results can't be pre-filled with placeholders
+        #[derive(Debug, PartialEq)]
+        struct Square(u64);
+        let output = parallel_map((0..50).collect(), 4, |n: u64| Square(n * n));
+        assert_eq!(output, (0..50).map(|n| Square(n * n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_par_map_builder() {
+        let input: Vec<u64> = (0..1000).collect();
+        let expected: Vec<u64> = input.iter().map(|n| n * n).collect();
+        let output = ParMap::new()
+            .threads(3)
+            .chunk_size(64)
+            .bounded(2)
+            .stack_size(256 * 1024)
+            .thread_name("square")
+            .map(input.clone(), |n| n * n);
+        assert_eq!(output, expected);
+        assert_eq!(ParMap::new().map(input, |n| n * n), expected);
+        assert_eq!(ParMap::new().map(Vec::<u64>::new(), |n| n), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_par_map_iter() {
+        use std::sync::atomic::AtomicUsize;
+        // Inputs that count how many of them are alive at once, standing in for the memory they use
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        static PEAK: AtomicUsize = AtomicUsize::new(0);
+        struct Tracked(u64);
+        impl Tracked {
+            fn new(n: u64) -> Tracked {
+                let live = LIVE.fetch_add(1, Ordering::SeqCst) + 1;
+                PEAK.fetch_max(live, Ordering::SeqCst);
+                Tracked(n)
+            }
+        }
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                LIVE.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        let expected: Vec<u64> = (0..10_000).map(|n| n * 2).collect();
+        let par_map = ParMap::new().threads(2).bounded(4);
+
+        // map needs every input in memory up front, however small the queue
+        let output = par_map.map((0..10_000).map(Tracked::new).collect(), |t: Tracked| t.0 * 2);
+        assert_eq!(output, expected);
+        assert_eq!(PEAK.swap(0, Ordering::SeqCst), 10_000);
+
+        // map_iter only makes inputs as the queue has room: at most 4 queued, one per worker and
+        // one waiting in the producer
+        let output = par_map.map_iter((0..10_000).map(Tracked::new), |t: Tracked| t.0 * 2);
+        assert_eq!(output, expected);
+        assert!(PEAK.load(Ordering::SeqCst) <= 4 + 2 + 1, "peak of {} live inputs", PEAK.load(Ordering::SeqCst));
+
+        assert_eq!(ParMap::new().map_iter(0..0, |n: u8| n), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_in_order() {
+        let results = vec![(2, 'c'), (0, 'a'), (4, 'e'), (1, 'b'), (3, 'd')];
+        assert_eq!(InOrder::new(results.into_iter()).collect::<String>(), "abcde");
+
+        // Each output comes out as soon as its prefix is complete, not when the input runs out
+        let pulled = std::cell::Cell::new(0);
+        let results = [(1, 'b'), (0, 'a'), (2, 'c'), (3, 'd')].iter().copied().inspect(|_| pulled.set(pulled.get() + 1));
+        let mut ordered = InOrder::new(results);
+        assert_eq!(ordered.next(), Some('a'));
+        assert_eq!(pulled.get(), 2);
+        assert_eq!(ordered.next(), Some('b'));
+        assert_eq!(pulled.get(), 2);
+        assert_eq!(ordered.next(), Some('c'));
+        assert_eq!(pulled.get(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "no result for index 1")]
+    fn test_in_order_missing_result() {
+        InOrder::new(vec![(0, 'a'), (2, 'c')].into_iter()).for_each(drop);
+    }
+
+    #[test]
+    #[should_panic(expected = "delivered twice")]
+    fn test_in_order_duplicate_result() {
+        InOrder::new(vec![(0, 'a'), (0, 'b')].into_iter()).for_each(drop);
+    }
+
+    #[test]
+    fn test_par_map_stream() {
+        use std::sync::atomic::AtomicUsize;
+        static RELEASED: AtomicBool = AtomicBool::new(false);
+        static WAIT_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+        let input: Vec<usize> = (0..100).collect();
+        let mut stream = ParMap::new().threads(4).bounded(2).stream(input.clone(), |n| {
+            if n == 99 {
+                // Held back until the test has seen the start of the output
+                let start = Instant::now();
+                while !RELEASED.load(Ordering::SeqCst) {
+                    if start.elapsed() > Duration::from_secs(5) {
+                        WAIT_TIMED_OUT.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            n * 2
+        });
+        let first: Vec<usize> = stream.by_ref().take(10).collect();
+        RELEASED.store(true, Ordering::SeqCst);
+        let rest: Vec<usize> = stream.collect();
+        assert!(!WAIT_TIMED_OUT.load(Ordering::SeqCst), "the stream waited for the last result");
+        assert_eq!([first, rest].concat(), input.iter().map(|n| n * 2).collect::<Vec<_>>());
+
+        let mut unordered: Vec<(usize, usize)> = ParMap::new().chunk_size(7).stream_unordered(input.clone(), |n| n + 1).collect();
+        unordered.sort();
+        assert_eq!(unordered, input.iter().map(|&n| (n, n + 1)).collect::<Vec<_>>());
+        assert_eq!(ParMap::new().stream(Vec::<u8>::new(), |n| n).count(), 0);
+
+        // Dropping a stream early stops the workers instead of mapping everything
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let mut stream = ParMap::new().threads(2).bounded(1).stream((0..10_000).collect::<Vec<u32>>(), |n| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_micros(100));
+            n
+        });
+        assert_eq!(stream.next(), Some(0));
+        drop(stream);
+        thread::sleep(Duration::from_millis(50));
+        let calls = CALLS.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(CALLS.load(Ordering::SeqCst), calls);
+        assert!(calls < 10_000);
+    }
+
+    #[test]
+    fn test_parallel_map_mut() {
+        let mut values: Vec<u32> = (0..100_000).collect();
+        parallel_map_mut(&mut values, 7, |n| *n += 1);
+        assert!(values.iter().enumerate().all(|(i, &n)| n == i as u32 + 1));
+
+        let mut empty: Vec<u32> = Vec::new();
+        parallel_map_mut(&mut empty, 4, |n| *n += 1);
+        let mut few = vec![1, 2];
+        parallel_map_mut(&mut few, 8, |n| *n *= 10);
+        assert_eq!(few, vec![10, 20]);
+    }
+
+    #[test]
+    fn test_parallel_map_balanced() {
+        let input: Vec<u64> = (0..1000).collect();
+        let expected: Vec<u64> = input.iter().map(|n| n * 3).collect();
+        assert_eq!(parallel_map_balanced(input, 6, |n| n * 3), expected);
+        assert_eq!(parallel_map_balanced(Vec::<u64>::new(), 3, |n| n), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_parallel_map_balanced_skewed_workload() {
+        // The first quarter of the elements is expensive, so static chunking gives all of them
+        // to one thread while work stealing spreads them over all four
+        let skewed = |n: u64| {
+            if n < 4 {
+                thread::sleep(Duration::from_millis(50));
+            }
+            n
+        };
+        let input: Vec<u64> = (0..16).collect();
+
+        let started = Instant::now();
+        let chunked = ParMap::new().threads(4).chunk_size(4).map(input.clone(), skewed);
+        let chunked_time = started.elapsed();
+
+        let started = Instant::now();
+        let balanced = parallel_map_balanced(input.clone(), 4, skewed);
+        let balanced_time = started.elapsed();
+
+        assert_eq!(chunked, input);
+        assert_eq!(balanced, input);
+        assert!(balanced_time < chunked_time, "static chunks: {:?}, work stealing: {:?}", chunked_time, balanced_time);
+    }
+
+    #[test]
+    fn test_parallel_map_pipeline() {
+        let input: Vec<u64> = (0..500).collect();
+        let output = parallel_map_pipeline(input.clone(), 3, |n| n + 1, 2, |n| n.to_string());
+        let expected: Vec<String> = input.iter().map(|n| (n + 1).to_string()).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_parallel_map_timed() {
+        let input: Vec<u64> = (0..300).collect();
+        let (output, counts, elapsed) = parallel_map_timed(input.clone(), 4, |n| {
+            thread::sleep(Duration::from_micros(100));
+            n + 1
+        });
+        assert_eq!(output, input.iter().map(|n| n + 1).collect::<Vec<_>>());
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts.iter().sum::<usize>(), 300);
+        assert!(elapsed > Duration::from_micros(300 * 100 / 4));
+    }
+
+    #[test]
+    fn test_collect_in_order() {
+        assert_eq!(collect_in_order(vec![(2, 'c'), (0, 'a'), (1, 'b')], 3), vec!['a', 'b', 'c']);
+    }
+
+    #[test]
+    #[should_panic(expected = "no result for index 1")]
+    fn test_collect_in_order_dropped_result() {
+        collect_in_order(vec![(2, 'c'), (0, 'a')], 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "result for index 0 delivered twice")]
+    fn test_collect_in_order_duplicate_result() {
+        collect_in_order(vec![(0, 'a'), (0, 'a'), (1, 'b')], 2);
+    }
+
+    #[test]
+    fn test_parallel_map_with_timeout() {
+        let squares = parallel_map_with_timeout(vec![1, 2, 3, 4], 2, Duration::from_secs(5), |n| n * n);
+        assert_eq!(squares, Ok(vec![1, 4, 9, 16]));
+
+        let started = Instant::now();
+        let result = parallel_map_with_timeout(vec![0, 1, 2, 3], 4, Duration::from_millis(100), |n| {
+            if n == 2 {
+                thread::sleep(Duration::from_secs(2));
+            }
+            n
+        });
+        assert_eq!(result, Err(TaskTimedOut { index: 2 }));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_try_parallel_map() {
+        use std::sync::atomic::AtomicUsize;
+        let parse = |s: &str| s.parse::<i32>().map_err(|_| format!("bad number {}", s));
+        let input = vec!["1", "2", "3", "4"];
+        assert_eq!(try_parallel_map(input, 3, parse), Ok(vec![1, 2, 3, 4]));
+        let input = vec!["1", "2", "x", "4"];
+        assert_eq!(try_parallel_map(input, 3, parse), Err(String::from("bad number x")));
+
+        // An early failure stops the rest of the work from being started
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let result = try_parallel_map((0..1000).collect(), 2, |n: usize| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+            if n == 10 { Err(n) } else { Ok(n) }
+        });
+        assert_eq!(result, Err(10));
+        assert!(CALLS.load(Ordering::SeqCst) < 100);
+    }
+
+    #[test]
+    fn test_parallel_map_to_map() {
+        let words = vec!["a", "bb", "ccc", "dd", "e"];
+        let lengths = parallel_map_to_map(words, 3, |word| (word.len(), word));
+        assert_eq!(lengths.len(), 3);
+        // "e" and "dd" come after "a" and "bb", so they win the shared keys
+        assert_eq!(lengths[&1], "e");
+        assert_eq!(lengths[&2], "dd");
+        assert_eq!(lengths[&3], "ccc");
+    }
+
+    /// CPU time in clock ticks used so far by each thread of this process with the given name,
+    /// read from /proc.
+    #[cfg(target_os = "linux")]
+    fn thread_cpu_ticks(name: &str) -> Vec<u64> {
+        let mut ticks = Vec::new();
+        for task in std::fs::read_dir("/proc/self/task").unwrap() {
+            let path = task.unwrap().path();
+            if std::fs::read_to_string(path.join("comm")).unwrap_or_default() != name {
+                continue;
+            }
+            let stat = std::fs::read_to_string(path.join("stat")).unwrap();
+            // utime and stime are the 14th and 15th fields, counting the ones before the
+            // parenthesized command name
+            let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 1..].split_whitespace().collect();
+            ticks.push(fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap());
+        }
+        ticks
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_waiting_workers_do_not_spin() {
+        // A slow single-threaded stage 1 leaves the four stage-2 workers waiting on the channel
+        // most of the time. Unnamed threads inherit this thread's name, so that finds them (and
+        // the mostly sleeping others) in /proc; sample their CPU time while they wait.
+        let name = std::fs::read_to_string("/proc/thread-self/comm").unwrap();
+        let monitor = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(450));
+            thread_cpu_ticks(&name)
+        });
+        let output = parallel_map_pipeline(
+            vec![1, 2, 3, 4],
+            1,
+            |n| {
+                thread::sleep(Duration::from_millis(150));
+                n
+            },
+            4,
+            |n| n * 10,
+        );
+        assert_eq!(output, vec![10, 20, 30, 40]);
+        let ticks = monitor.join().unwrap();
+        assert!(ticks.len() >= 6, "expected to find the workers, found {} threads", ticks.len());
+        // Four spinning workers would have used about 4 * 450ms by now; a tick is usually 10ms
+        let total: u64 = ticks.iter().sum();
+        assert!(total < 20, "waiting threads used {} ticks of CPU time", total);
+    }
+}